@@ -1,13 +1,48 @@
 /// Command-line interface definition.
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
 
 // Enum for the restore target, which is safer than a raw string.
 #[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
-#[serde(rename_all = "lowercase")]
+#[serde(rename_all = "kebab-case")]
 pub enum RestoreTarget {
+    /// The focused workspace.
     Active,
+    /// The workspace the window was minimized from.
     Original,
+    /// The workspace shown on the focused monitor.
+    FocusedMonitor,
+    /// The workspace shown on the monitor under the cursor.
+    CursorMonitor,
+    /// The workspace now shown on the monitor the window was minimized from.
+    OriginalMonitor,
+}
+
+// Enum for how the daemon shows the minimized windows in the tray.
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrayMode {
+    /// One icon for each minimized window.
+    PerWindow,
+    /// A single icon, with a menu listing every minimized window.
+    Single,
+    /// An icon for each window class, with a submenu for each of its windows.
+    PerClass,
+}
+
+// Enum for the way hyprland-minimizer talks to Hyprland.
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IpcBackend {
+    Socket,
+    Hyprctl,
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum Commands {
+    /// Run a single long-lived process that hosts the tray icons of all minimized
+    /// windows. Other invocations forward their requests to it while it runs.
+    Daemon,
 }
 
 #[derive(Parser, Debug, Serialize, Clone)]
@@ -21,6 +56,16 @@ It works by moving windows to a special workspace and creating a D-Bus service t
 )]
 #[serde(rename_all = "lowercase")]
 pub struct Args {
+    #[command(subcommand)]
+    #[serde(skip)]
+    pub command: Option<Commands>,
+
+    /// The Hyprland instance to talk to, like `hyprctl -i`: its signature, or its number
+    /// in `hyprctl instances`. Defaults to the instance from $HYPRLAND_INSTANCE_SIGNATURE.
+    #[arg(long, short = 'i')]
+    #[serde(skip)]
+    pub instance: Option<String>,
+
     /// The launcher used for menu selection of windows to restore. Must follow dmenu
     /// syntax.
     #[arg(long, short = 'l')]
@@ -32,12 +77,13 @@ pub struct Args {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub window_address: Option<String>,
 
-    /// The workspace to restore the window to: active or original.
+    /// The workspace to restore the window to. The monitor targets fall back to the
+    /// focused monitor when theirs is gone.
     #[arg(long, short = 't')]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub restore_to: Option<RestoreTarget>,
 
-    /// The base directory to store the stack tmp file.
+    /// The base directory to store the stack file in, instead of $XDG_RUNTIME_DIR/hyprland-minimizer.
     #[arg(long, short = 's')]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stack_base_directory: Option<String>,
@@ -48,10 +94,44 @@ pub struct Args {
     pub workspace: Option<String>,
 
     /// The poll interval used to check weither the window is still minimized (milliseconds).
+    /// Only used when Hyprland's event socket is unavailable.
     #[arg(long, short = 'p')]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub poll_interval_ms: Option<u64>,
 
+    /// How to talk to Hyprland: socket (its IPC socket directly) or hyprctl.
+    #[arg(long, short = 'b')]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipc_backend: Option<IpcBackend>,
+
+    /// How long to wait for Hyprland to answer a single request (milliseconds).
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipc_timeout_ms: Option<u64>,
+
+    /// How many times to retry a request to Hyprland after a transient failure.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipc_retries: Option<u32>,
+
+    /// A window class whose geometry and fullscreen mode are not restored. Can be
+    /// repeated, or given as a comma-separated list.
+    #[arg(long, value_name = "CLASS", value_delimiter = ',')]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preserve_state_exclude: Option<Vec<String>>,
+
+    /// How the daemon shows the minimized windows in the tray: an icon per window, per
+    /// window class, or a single one. Windows minimized without the daemon always get their own icon.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tray_mode: Option<TrayMode>,
+
+    /// Minimize a grouped window along with the rest of its group, behind a single tray
+    /// icon, and restore them together.
+    #[arg(long, action, default_value_t = false)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub minimize_whole_group: bool,
+
     /// Unminimize on focus. Set it to true to integrate with docks like hypr-dock.
     #[arg(long, short = 'a', action, default_value_t = false)]
     pub auto_unminimize_on_focus: bool,
@@ -80,12 +160,20 @@ mod tests {
         // Create an instance of Args where some optional fields have values
         // and others are None (the default).
         let args = Args {
+            command: None,
+            instance: None,
             window_address: Some("0x123".to_string()),
             launcher: None,
             stack_base_directory: None,
             workspace: None,
             restore_to: Some(RestoreTarget::Original),
             poll_interval_ms: None,
+            ipc_backend: None,
+            ipc_timeout_ms: None,
+            ipc_retries: None,
+            preserve_state_exclude: None,
+            tray_mode: None,
+            minimize_whole_group: false,
             auto_unminimize_on_focus: false,
             restore_last: false,
             generate_config_file: false,
@@ -114,6 +202,13 @@ mod tests {
         assert!(!obj.contains_key("launcher"));
         assert!(!obj.contains_key("stack_base_directory"));
         assert!(!obj.contains_key("poll_interval_ms"));
+        assert!(!obj.contains_key("ipc_backend"));
+        assert!(!obj.contains_key("ipc_timeout_ms"));
+        assert!(!obj.contains_key("ipc_retries"));
+        assert!(!obj.contains_key("preserve_state_exclude"));
+        assert!(!obj.contains_key("tray_mode"));
+        assert!(!obj.contains_key("minimize_whole_group"));
         assert!(!obj.contains_key("command"));
+        assert!(!obj.contains_key("instance"));
     }
 }
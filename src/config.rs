@@ -1,9 +1,10 @@
 //! Allows parsing of the config file
-use crate::cli::RestoreTarget;
+use crate::cli::{IpcBackend, RestoreTarget, TrayMode};
 
 use anyhow::{Context, Result};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -15,7 +16,14 @@ pub struct Config {
     pub workspace: Option<String>,
     pub restore_to: Option<RestoreTarget>,
     pub poll_interval_ms: Option<u64>,
+    pub ipc_backend: Option<IpcBackend>,
+    pub ipc_timeout_ms: Option<u64>,
+    pub ipc_retries: Option<u32>,
     pub auto_unminimize_on_focus: Option<bool>,
+    pub preserve_state_exclude: Option<Vec<String>>,
+    pub minimize_whole_group: Option<bool>,
+    pub tray_mode: Option<TrayMode>,
+    pub icon_overrides: Option<HashMap<String, String>>,
 }
 
 // This ensures that Config::default() uses our custom default values.
@@ -23,24 +31,38 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             launcher: Some(default_launcher()),
-            stack_base_directory: Some(default_stack_base_directory()),
+            stack_base_directory: None,
             workspace: Some(default_workspace()),
             restore_to: Some(default_restore_target()),
             poll_interval_ms: Some(default_poll_interval()),
+            ipc_backend: Some(default_ipc_backend()),
+            ipc_timeout_ms: Some(default_ipc_timeout()),
+            ipc_retries: Some(default_ipc_retries()),
             auto_unminimize_on_focus: Some(default_unminimize_on_focus()),
+            preserve_state_exclude: Some(default_preserve_state_exclude()),
+            minimize_whole_group: Some(default_minimize_whole_group()),
+            tray_mode: Some(default_tray_mode()),
+            icon_overrides: Some(default_icon_overrides()),
         }
     }
 }
+
+impl Config {
+    /// Whether windows of `class` get their geometry and fullscreen mode back when
+    /// restored.
+    pub fn preserves_state_of(&self, class: &str) -> bool {
+        !self
+            .preserve_state_exclude
+            .as_ref()
+            .is_some_and(|classes| classes.iter().any(|c| c == class))
+    }
+}
 // --- Default value functions for serde ---
 
 fn default_launcher() -> String {
     "wofi -dmenu".to_string()
 }
 
-fn default_stack_base_directory() -> String {
-    "/tmp".to_string()
-}
-
 fn default_workspace() -> String {
     "special:minimized".to_string()
 }
@@ -53,10 +75,38 @@ fn default_poll_interval() -> u64 {
     2000
 }
 
+fn default_ipc_backend() -> IpcBackend {
+    IpcBackend::Socket
+}
+
+fn default_ipc_timeout() -> u64 {
+    2000
+}
+
+fn default_ipc_retries() -> u32 {
+    2
+}
+
 fn default_unminimize_on_focus() -> bool {
     false
 }
 
+fn default_preserve_state_exclude() -> Vec<String> {
+    Vec::new()
+}
+
+fn default_minimize_whole_group() -> bool {
+    false
+}
+
+fn default_tray_mode() -> TrayMode {
+    TrayMode::PerWindow
+}
+
+fn default_icon_overrides() -> HashMap<String, String> {
+    HashMap::new()
+}
+
 /// Finds the project's configuration directory using XDG standards.
 pub fn get_config_dir() -> Result<PathBuf> {
     let Some(proj_dirs) = ProjectDirs::from("fr", "denischevalier", "hyprland-minimizer") else {
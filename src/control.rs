@@ -0,0 +1,503 @@
+//! The daemon's local control socket, speaking newline-delimited JSON.
+//!
+//! See `doc/control-protocol.md` for the schema.
+use crate::cli::RestoreTarget;
+use crate::daemon::{Daemon, DaemonEvent};
+use crate::stack::{StackEntry, ensure_private_dir, runtime_directory};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Duration;
+
+/// How long to wait before accepting clients again after a failure.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// A request sent by a client, one JSON object per line.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    Minimize {
+        address: Option<String>,
+    },
+    Restore {
+        address: String,
+        target: Option<RestoreTarget>,
+    },
+    RestoreLast,
+    List,
+    Close {
+        address: String,
+    },
+    Subscribe,
+}
+
+/// The reply to a request, one JSON object per line.
+#[derive(Serialize, Debug, Default)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub windows: Option<Vec<StackEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Response {
+    fn ok() -> Self {
+        Response {
+            ok: true,
+            ..Default::default()
+        }
+    }
+
+    fn from_result(result: Result<()>) -> Self {
+        match result {
+            Ok(()) => Response::ok(),
+            Err(e) => Response::error(format!("{e:#}")),
+        }
+    }
+
+    fn error(message: String) -> Self {
+        Response {
+            ok: false,
+            error: Some(message),
+            ..Default::default()
+        }
+    }
+}
+
+/// The path of the control socket: `control.sock` in the runtime directory of the
+/// stack files, or `control-<instance>.sock` for a daemon serving a known Hyprland
+/// instance.
+pub fn socket_path(instance: Option<&str>) -> PathBuf {
+    let name = match instance {
+        Some(instance) => format!("control-{instance}.sock"),
+        None => "control.sock".to_string(),
+    };
+    runtime_directory().join(name)
+}
+
+/// Binds the control socket, replacing a stale one left behind by a previous daemon.
+pub fn bind(path: &Path) -> Result<UnixListener> {
+    if let Some(dir) = path.parent() {
+        ensure_private_dir(dir)
+            .with_context(|| format!("Failed to create control socket directory {:?}", dir))?;
+    }
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        bail!("Another daemon is already listening on {:?}", path);
+    }
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            return Err(e).with_context(|| format!("Failed to remove stale socket {:?}", path));
+        }
+        _ => {}
+    }
+    UnixListener::bind(path).with_context(|| format!("Failed to bind control socket {:?}", path))
+}
+
+/// Accepts clients on the control socket forever, serving each one concurrently.
+pub async fn serve(daemon: Arc<Daemon>, listener: UnixListener) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle_client(Arc::clone(&daemon), stream));
+            }
+            Err(e) => {
+                // Errors like running out of file descriptors persist for a while:
+                // back off instead of spinning on them.
+                eprintln!("[Error] Failed to accept a control client: {e}");
+                tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Answers the requests of a single client until it disconnects or subscribes.
+async fn handle_client(daemon: Arc<Daemon>, stream: UnixStream) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                let response = Response::error(format!("Invalid request: {e}"));
+                if write_line(&mut writer, &response).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+
+        if request == Request::Subscribe {
+            stream_events(&daemon, &mut writer).await;
+            return;
+        }
+
+        let response = handle_request(&daemon, request).await;
+        if write_line(&mut writer, &response).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn handle_request(daemon: &Arc<Daemon>, request: Request) -> Response {
+    match request {
+        Request::Minimize { address } => {
+            Response::from_result(Daemon::minimize(daemon, address.as_deref()).await)
+        }
+        Request::Restore { address, target } => {
+            Response::from_result(daemon.restore(&address, target).await)
+        }
+        Request::RestoreLast => Response::from_result(daemon.restore_last().await),
+        Request::List => match daemon.list().await {
+            Ok(windows) => Response {
+                windows: Some(windows),
+                ..Response::ok()
+            },
+            Err(e) => Response::error(format!("{e:#}")),
+        },
+        Request::Close { address } => Response::from_result(daemon.close(&address).await),
+        Request::Subscribe => unreachable!("subscriptions are handled by the caller"),
+    }
+}
+
+/// Acknowledges a subscription, then forwards every daemon event until the client
+/// goes away.
+async fn stream_events<W: AsyncWrite + Unpin>(daemon: &Daemon, writer: &mut W) {
+    let mut events = daemon.subscribe();
+    if write_line(writer, &Response::ok()).await.is_err() {
+        return;
+    }
+    loop {
+        let event: DaemonEvent = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+        if write_line(writer, &event).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn write_line<W: AsyncWrite + Unpin>(writer: &mut W, value: &impl Serialize) -> Result<()> {
+    let mut line = serde_json::to_vec(value).context("Failed to serialize reply")?;
+    line.push(b'\n');
+    writer
+        .write_all(&line)
+        .await
+        .context("Failed to write to control client")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::daemon::tests::PeerDbus;
+    use crate::exit;
+    use crate::hyprland::{self, Hyprland, WindowInfo};
+    use crate::minimize::{DbusConnection, MinimizeError};
+    use crate::stack::Stack;
+    use async_trait::async_trait;
+    use serde_json::{Value, json};
+    use std::collections::HashMap;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{ExitStatus, Output};
+    use std::sync::Mutex;
+    use tempfile::{NamedTempFile, TempDir, tempdir};
+    use tokio::io::{Lines, ReadHalf, WriteHalf};
+    use tokio::sync::Notify;
+    use tokio::time::{Duration, timeout};
+
+    // --- Mocking Setup ---
+
+    /// Answers each json command with a configurable reply, defaulting to `[]`.
+    #[derive(Default, Clone)]
+    struct MockExecutor {
+        dispatched_commands: Arc<Mutex<Vec<String>>>,
+        dispatched_batches: Arc<Mutex<Vec<Vec<String>>>>,
+        json_responses: Arc<Mutex<HashMap<String, String>>>,
+    }
+    impl MockExecutor {
+        fn set_json_response(&self, command: &str, json: &str) {
+            self.json_responses
+                .lock()
+                .unwrap()
+                .insert(command.to_string(), json.to_string());
+        }
+        fn dispatched_commands(&self) -> Vec<String> {
+            self.dispatched_commands.lock().unwrap().clone()
+        }
+        fn dispatched_batches(&self) -> Vec<Vec<String>> {
+            self.dispatched_batches.lock().unwrap().clone()
+        }
+    }
+    #[async_trait]
+    impl hyprland::HyprctlExecutor for MockExecutor {
+        async fn execute_json(&self, command: &str) -> Result<Output> {
+            let response = self
+                .json_responses
+                .lock()
+                .unwrap()
+                .get(command)
+                .cloned()
+                .unwrap_or_else(|| "[]".to_string());
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: response.into_bytes(),
+                stderr: vec![],
+            })
+        }
+        async fn execute_dispatch(&self, command: &str) -> Result<Output> {
+            self.dispatched_commands
+                .lock()
+                .unwrap()
+                .push(command.to_string());
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: vec![],
+                stderr: vec![],
+            })
+        }
+        async fn execute_batch(&self, commands: &[String]) -> Result<Output> {
+            self.dispatched_batches
+                .lock()
+                .unwrap()
+                .push(commands.to_vec());
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: vec![],
+                stderr: vec![],
+            })
+        }
+    }
+
+    /// A bus without a tray: icons are served but can never be registered.
+    struct NoWatcherDbus(PeerDbus);
+    #[async_trait]
+    impl DbusConnection for NoWatcherDbus {
+        async fn setup(
+            &self,
+            window_info: &WindowInfo,
+            exit_notify: Arc<Notify>,
+            hyprland: &Hyprland,
+        ) -> Result<Option<(Arc<zbus::Connection>, String)>> {
+            self.0.setup(window_info, exit_notify, hyprland).await
+        }
+        async fn register(
+            &self,
+            _connection: &Arc<zbus::Connection>,
+            _item_path: &str,
+        ) -> Result<()> {
+            Err(MinimizeError::NoTrayWatcher.into())
+        }
+    }
+
+    const MINIMIZED_CLIENTS: &str = r#"[{"address": "0xCONTROL", "workspace": {"id": -98, "name": "special:minimized"}, "title": "Test", "class": "Test"}]"#;
+
+    /// An in-process client talking to the control socket.
+    struct Client {
+        lines: Lines<BufReader<ReadHalf<UnixStream>>>,
+        writer: WriteHalf<UnixStream>,
+    }
+
+    impl Client {
+        async fn connect(path: &Path) -> Self {
+            let stream = UnixStream::connect(path).await.unwrap();
+            let (reader, writer) = tokio::io::split(stream);
+            Client {
+                lines: BufReader::new(reader).lines(),
+                writer,
+            }
+        }
+
+        async fn send(&mut self, request: &str) {
+            self.writer.write_all(request.as_bytes()).await.unwrap();
+            self.writer.write_all(b"\n").await.unwrap();
+        }
+
+        async fn receive(&mut self) -> Value {
+            let line = timeout(Duration::from_secs(2), self.lines.next_line())
+                .await
+                .expect("timed out waiting for the daemon")
+                .unwrap()
+                .unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+
+        async fn request(&mut self, request: &str) -> Value {
+            self.send(request).await;
+            self.receive().await
+        }
+    }
+
+    /// Starts a daemon with a mocked Hyprland and its control socket in a temporary directory.
+    async fn start_daemon() -> (TempDir, NamedTempFile, PathBuf, MockExecutor) {
+        let dir = tempdir().unwrap();
+        let stack_file = NamedTempFile::new().unwrap();
+        let executor = MockExecutor::default();
+        let config = Config {
+            poll_interval_ms: Some(20),
+            ..Config::default()
+        };
+        let daemon = Daemon::new(
+            config,
+            Stack::new(stack_file.path()),
+            Hyprland::new(Arc::new(executor.clone())),
+            Arc::new(PeerDbus::new().await),
+        );
+
+        let path = dir.path().join("hyprland-minimizer").join("control.sock");
+        let listener = bind(&path).unwrap();
+        tokio::spawn(serve(daemon, listener));
+        (dir, stack_file, path, executor)
+    }
+
+    #[test]
+    fn test_request_parsing() {
+        assert_eq!(
+            serde_json::from_str::<Request>(
+                r#"{"command": "restore", "address": "0x1", "target": "original"}"#
+            )
+            .unwrap(),
+            Request::Restore {
+                address: "0x1".to_string(),
+                target: Some(RestoreTarget::Original),
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"command": "minimize"}"#).unwrap(),
+            Request::Minimize { address: None }
+        );
+        assert!(serde_json::from_str::<Request>(r#"{"command": "explode"}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_request_keeps_connection_open() {
+        let (_dir, _stack, path, _executor) = start_daemon().await;
+        let mut client = Client::connect(&path).await;
+
+        let response = client.request("not json").await;
+        assert_eq!(response["ok"], json!(false));
+        assert!(
+            response["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("Invalid request")
+        );
+
+        let response = client.request(r#"{"command": "list"}"#).await;
+        assert_eq!(response, json!({"ok": true, "windows": []}));
+    }
+
+    #[tokio::test]
+    async fn test_minimize_list_restore_and_subscribe() {
+        let (_dir, _stack, path, executor) = start_daemon().await;
+        executor.set_json_response("clients", MINIMIZED_CLIENTS);
+        executor.set_json_response("activeworkspace", r#"{"id": 4}"#);
+
+        let mut subscriber = Client::connect(&path).await;
+        assert_eq!(
+            subscriber.request(r#"{"command": "subscribe"}"#).await,
+            json!({"ok": true})
+        );
+
+        let mut client = Client::connect(&path).await;
+        let response = client
+            .request(r#"{"command": "minimize", "address": "0xCONTROL"}"#)
+            .await;
+        assert_eq!(response, json!({"ok": true}));
+
+        let event = subscriber.receive().await;
+        assert_eq!(event["event"], json!("minimized"));
+        assert_eq!(event["window"]["address"], json!("0xCONTROL"));
+
+        // Minimizing the same window twice is refused.
+        let response = client
+            .request(r#"{"command": "minimize", "address": "0xCONTROL"}"#)
+            .await;
+        assert_eq!(response["ok"], json!(false));
+
+        let response = client.request(r#"{"command": "list"}"#).await;
+        assert_eq!(response["windows"][0]["address"], json!("0xCONTROL"));
+
+        let response = client
+            .request(r#"{"command": "restore", "address": "0xCONTROL"}"#)
+            .await;
+        assert_eq!(response, json!({"ok": true}));
+        assert!(executor.dispatched_batches().contains(&vec![
+            "movetoworkspace 4,address:0xCONTROL".to_string(),
+            "focuswindow address:0xCONTROL".to_string()
+        ]));
+
+        // Hyprland now reports the window as restored, so the daemon lets it go.
+        executor.set_json_response(
+            "clients",
+            r#"[{"address": "0xCONTROL", "workspace": {"id": 4}, "title": "Test", "class": "Test"}]"#,
+        );
+        let event = subscriber.receive().await;
+        assert_eq!(event, json!({"event": "restored", "address": "0xCONTROL"}));
+    }
+
+    #[tokio::test]
+    async fn test_close_and_restore_require_a_hosted_window() {
+        let (_dir, _stack, path, executor) = start_daemon().await;
+        let mut client = Client::connect(&path).await;
+
+        let response = client
+            .request(r#"{"command": "close", "address": "0xUNKNOWN"}"#)
+            .await;
+        assert_eq!(response["ok"], json!(false));
+
+        let response = client
+            .request(r#"{"command": "restore", "address": "0xUNKNOWN", "target": "active"}"#)
+            .await;
+        assert_eq!(response["ok"], json!(false));
+
+        assert!(executor.dispatched_commands().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_bind_refuses_a_live_socket() {
+        let (_dir, _stack, path, _executor) = start_daemon().await;
+        assert!(bind(&path).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_minimize_reports_a_missing_tray_watcher() {
+        let stack_file = NamedTempFile::new().unwrap();
+        let executor = MockExecutor::default();
+        executor.set_json_response(
+            "activewindow",
+            r#"{"address": "0xCONTROL", "workspace": {"id": 4}, "title": "Test", "class": "Test"}"#,
+        );
+        let daemon = Daemon::new(
+            Config::default(),
+            Stack::new(stack_file.path()),
+            Hyprland::new(Arc::new(executor.clone())),
+            Arc::new(NoWatcherDbus(PeerDbus::new().await)),
+        );
+        let mut events = daemon.subscribe();
+
+        let error = Daemon::minimize(&daemon, None).await.unwrap_err();
+        assert_eq!(exit::code(&error), exit::NO_TRAY_WATCHER);
+
+        // The window went back to where it was and nothing claims it is minimized.
+        assert!(events.try_recv().is_err());
+        assert!(daemon.list().await.unwrap().is_empty());
+        assert!(executor.dispatched_batches().contains(&vec![
+            "movetoworkspace 4,address:0xCONTROL".to_string(),
+            "focuswindow address:0xCONTROL".to_string()
+        ]));
+    }
+}
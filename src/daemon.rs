@@ -0,0 +1,559 @@
+//! A single long-running process that hosts the tray icons of every minimized window.
+use crate::cli::{RestoreTarget, TrayMode};
+use crate::config::Config;
+use crate::control;
+use crate::dbus::aggregate::{self, AggregateItem, AggregateMenu, MinimizedWindows};
+use crate::dbus::control::{BUS_NAME, ControlInterface, ControlProxy, OBJECT_PATH, emit_signals};
+use crate::dbus::{DbusMenu, StatusNotifierItem};
+use crate::hyprland::{Hyprland, WindowInfo};
+use crate::icons::IconResolver;
+use crate::minimize::{
+    DbusConnection, Minimizer, register_with_watcher, restorable, shutdown_signal,
+};
+use crate::restore::{restore_last_minimized, restore_to_outside};
+use crate::stack::{Stack, StackEntry};
+
+use anyhow::{Context, Result, anyhow, bail};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Notify, broadcast, oneshot};
+use tokio::time::{Duration, Instant, sleep};
+use zbus::names::BusName;
+use zbus::zvariant::OwnedObjectPath;
+use zbus::{Connection, ConnectionBuilder};
+
+/// How long to wait on shutdown for the minimized windows to restore themselves.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+
+/// Something that happened to a window hosted by the daemon.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DaemonEvent {
+    /// The window was moved to the minimized workspace and got a tray icon.
+    Minimized { window: StackEntry },
+    /// The window left the tray, either restored or closed.
+    Restored { address: String },
+}
+
+/// The state shared by every window hosted by the daemon.
+pub struct Daemon {
+    config: Config,
+    stack: Stack,
+    hyprland: Hyprland,
+    dbus: Arc<dyn DbusConnection>,
+    // The state of each hosted window at the time it was minimized.
+    windows: Mutex<HashMap<String, StackEntry>>,
+    events: broadcast::Sender<DaemonEvent>,
+}
+
+impl Daemon {
+    pub fn new(
+        config: Config,
+        stack: Stack,
+        hyprland: Hyprland,
+        dbus: Arc<dyn DbusConnection>,
+    ) -> Arc<Self> {
+        let (events, _) = broadcast::channel(64);
+        Arc::new(Daemon {
+            config,
+            stack,
+            hyprland,
+            dbus,
+            windows: Mutex::new(HashMap::new()),
+            events,
+        })
+    }
+
+    /// Acquires the daemon's bus name and control socket, and serves requests until
+    /// interrupted.
+    pub async fn run(config: Config, stack: Stack, hyprland: Hyprland) -> Result<()> {
+        let connection = Arc::new(
+            ConnectionBuilder::session()?
+                .build()
+                .await
+                .context("Failed to connect to the session bus.")?,
+        );
+        let dbus: Arc<dyn DbusConnection> = match config.tray_mode.unwrap() {
+            TrayMode::PerWindow => Arc::new(
+                SharedDbus::new(Arc::clone(&connection))
+                    .with_icons(IconResolver::new(config.icon_overrides.clone().unwrap())),
+            ),
+            mode => Arc::new(AggregateDbus::new(
+                Arc::clone(&connection),
+                config.clone(),
+                stack.clone(),
+                hyprland.clone(),
+                mode == TrayMode::PerClass,
+            )),
+        };
+        let daemon = Daemon::new(config, stack, hyprland, dbus);
+        Daemon::serve_dbus(&daemon, &connection).await?;
+
+        let socket_path = control::socket_path(daemon.hyprland.instance());
+        let listener = control::bind(&socket_path)?;
+        tokio::spawn(control::serve(Arc::clone(&daemon), listener));
+
+        println!("Daemon started. Waiting for requests...");
+        shutdown_signal().await?;
+
+        // Every hosted window restores itself on the same signal; give them a moment
+        // to do so.
+        let deadline = Instant::now() + SHUTDOWN_GRACE;
+        while !daemon.windows.lock().unwrap().is_empty() && Instant::now() < deadline {
+            sleep(Duration::from_millis(50)).await;
+        }
+        let _ = fs::remove_file(&socket_path);
+        println!("Exiting.");
+
+        Ok(())
+    }
+
+    /// Serves the control interface next to the tray objects, acquires the daemon's
+    /// bus name and starts relaying events as signals.
+    pub async fn serve_dbus(daemon: &Arc<Self>, connection: &Arc<Connection>) -> Result<()> {
+        connection
+            .object_server()
+            .at(OBJECT_PATH, ControlInterface::new(Arc::clone(daemon)))
+            .await?;
+        connection
+            .request_name(BUS_NAME)
+            .await
+            .context("Failed to acquire the daemon's bus name. Is another daemon running?")?;
+        tokio::spawn(emit_signals(Arc::clone(connection), daemon.subscribe()));
+        Ok(())
+    }
+
+    /// The signature of the Hyprland instance the daemon serves, if known.
+    pub fn instance(&self) -> Option<&str> {
+        self.hyprland.instance()
+    }
+
+    /// Subscribes to the events of every window hosted by the daemon.
+    pub fn subscribe(&self) -> broadcast::Receiver<DaemonEvent> {
+        self.events.subscribe()
+    }
+
+    /// Minimizes a window, or the active one when no address is given, and hosts its
+    /// tray icon until it is restored or closed.
+    pub async fn minimize(daemon: &Arc<Self>, address: Option<&str>) -> Result<()> {
+        let window_info = match address {
+            Some(address) => daemon.hyprland.get_window_by_address(address).await?,
+            None => daemon.hyprland.active_window().await?,
+        };
+        let address = window_info.address.clone();
+        let entry = StackEntry::from_window(&restorable(&daemon.config, &window_info));
+        {
+            let mut windows = daemon.windows.lock().unwrap();
+            if windows.contains_key(&address) {
+                bail!("Window {address} is already minimized.");
+            }
+            windows.insert(address.clone(), entry.clone());
+        }
+
+        // The window is hidden and its icon registered before replying, so failures
+        // reach the caller; only the wait for its restore runs in the background.
+        let (hidden_tx, hidden_rx) = oneshot::channel();
+        let task_daemon = Arc::clone(daemon);
+        tokio::spawn(async move {
+            let daemon = task_daemon;
+            let minimizer = Minimizer::new(
+                daemon.config.clone(),
+                &daemon.stack,
+                window_info,
+                daemon.hyprland.clone(),
+                &*daemon.dbus,
+            );
+            let minimized = match minimizer.hide().await {
+                Ok(minimized) => minimized,
+                Err(e) => {
+                    daemon.windows.lock().unwrap().remove(&address);
+                    let _ = hidden_tx.send(Err(e));
+                    return;
+                }
+            };
+            let _ = daemon.events.send(DaemonEvent::Minimized { window: entry });
+            let _ = hidden_tx.send(Ok(()));
+
+            if let Err(e) = minimized.wait().await {
+                eprintln!("[Error] Failed to watch window {address}: {e:#}");
+            }
+            daemon.windows.lock().unwrap().remove(&address);
+            let _ = daemon.events.send(DaemonEvent::Restored { address });
+        });
+
+        hidden_rx
+            .await
+            .context("The minimizing task stopped before reporting back.")?
+    }
+
+    /// Restores a hosted window to the given workspace, or the configured one, but
+    /// never into the minimized workspace. Its tray icon goes away once the window
+    /// watcher notices it has left the minimized workspace.
+    pub async fn restore(&self, address: &str, target: Option<RestoreTarget>) -> Result<()> {
+        let window = self.hosted_window(address)?;
+        restore_to_outside(
+            &self.hyprland,
+            &window,
+            target.unwrap_or(self.config.restore_to.unwrap()),
+            self.config.workspace.as_deref().unwrap(),
+        )
+        .await
+    }
+
+    /// Restores the last minimized window.
+    pub async fn restore_last(&self) -> Result<()> {
+        restore_last_minimized(self.config.clone(), &self.stack, &self.hyprland).await
+    }
+
+    /// Lists the minimized windows, most recently minimized last.
+    pub async fn list(&self) -> Result<Vec<StackEntry>> {
+        self.stack.minimized(&self.hyprland).await
+    }
+
+    /// Closes a hosted window.
+    pub async fn close(&self, address: &str) -> Result<()> {
+        let window = self.hosted_window(address)?;
+        self.hyprland
+            .dispatch(&format!("closewindow address:{}", window.address))
+            .await
+    }
+
+    fn hosted_window(&self, address: &str) -> Result<StackEntry> {
+        self.windows
+            .lock()
+            .unwrap()
+            .get(address)
+            .cloned()
+            .ok_or_else(|| anyhow!("Window {address} is not minimized by the daemon."))
+    }
+}
+
+/// Connects to a running daemon serving the given Hyprland instance, or returns
+/// `None` when there is none to forward to.
+pub async fn connect(instance: Option<&str>) -> Option<ControlProxy<'static>> {
+    let connection = Connection::session().await.ok()?;
+    let dbus = zbus::fdo::DBusProxy::new(&connection).await.ok()?;
+    let name = BusName::try_from(BUS_NAME).ok()?;
+    if !dbus.name_has_owner(name).await.ok()? {
+        return None;
+    }
+    let proxy = ControlProxy::new(&connection).await.ok()?;
+    // A daemon started from another session would act on the wrong windows.
+    if proxy.instance().await.ok()? != instance.unwrap_or_default() {
+        return None;
+    }
+    Some(proxy)
+}
+
+fn item_path(address: &str) -> Result<OwnedObjectPath> {
+    OwnedObjectPath::try_from(format!("/StatusNotifierItem/{address}"))
+        .with_context(|| format!("Invalid window address for a D-Bus path: {address}"))
+}
+
+fn menu_path(address: &str) -> Result<OwnedObjectPath> {
+    OwnedObjectPath::try_from(format!("/Menu/{address}"))
+        .with_context(|| format!("Invalid window address for a D-Bus path: {address}"))
+}
+
+/// Serves the tray objects of every window on the daemon's single connection,
+/// each under its own path, and registers them with the watcher by path.
+pub struct SharedDbus {
+    connection: Arc<Connection>,
+    icons: IconResolver,
+}
+
+impl SharedDbus {
+    pub fn new(connection: Arc<Connection>) -> Self {
+        SharedDbus {
+            connection,
+            icons: IconResolver::default(),
+        }
+    }
+
+    /// Resolves the icons of the windows with `icons`, and its overrides.
+    pub fn with_icons(mut self, icons: IconResolver) -> Self {
+        self.icons = icons;
+        self
+    }
+}
+
+#[async_trait]
+impl DbusConnection for SharedDbus {
+    async fn setup(
+        &self,
+        window_info: &WindowInfo,
+        exit_notify: Arc<Notify>,
+        hyprland: &Hyprland,
+    ) -> Result<Option<(Arc<Connection>, String)>> {
+        let item_path = item_path(&window_info.address)?;
+        let menu_path = menu_path(&window_info.address)?;
+
+        let icon_name = self.icons.resolve(&window_info.class);
+        let notifier_item = StatusNotifierItem::new(
+            window_info.clone(),
+            Arc::clone(&exit_notify),
+            hyprland.clone(),
+        )
+        .with_icon_pixmap(self.icons.pixmaps(&icon_name, &window_info.class).await)
+        .with_icon_name(icon_name)
+        .with_menu_path(menu_path.clone());
+        let dbus_menu = DbusMenu::new(window_info.clone(), exit_notify, hyprland);
+
+        let object_server = self.connection.object_server();
+        object_server.at(&item_path, notifier_item).await?;
+        object_server.at(&menu_path, dbus_menu).await?;
+
+        Ok(Some((Arc::clone(&self.connection), item_path.to_string())))
+    }
+
+    async fn register(&self, connection: &Arc<Connection>, item_path: &str) -> Result<()> {
+        register_with_watcher(connection, item_path).await
+    }
+
+    async fn teardown(&self, connection: &Arc<Connection>, item_path: &str) -> Result<()> {
+        let address = item_path.trim_start_matches("/StatusNotifierItem/");
+        let object_server = connection.object_server();
+
+        // Watchers only drop items when their connection goes away, which never
+        // happens here. Going passive first makes hosts hide the icon instead.
+        let item = object_server
+            .interface::<_, StatusNotifierItem>(item_path)
+            .await?;
+        item.get_mut().await.set_passive();
+        StatusNotifierItem::new_status(item.signal_context(), "Passive").await?;
+
+        object_server
+            .remove::<StatusNotifierItem, _>(item_path)
+            .await?;
+        object_server
+            .remove::<DbusMenu, _>(menu_path(address)?)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Serves tray icons that each stand for several windows on the daemon's
+/// connection: a single one for every window, or one for each window class, with a
+/// menu listing them. Windows are keyed by a path under their icon's, which only
+/// stands for them towards the `Minimizer`.
+pub struct AggregateDbus {
+    connection: Arc<Connection>,
+    config: Config,
+    stack: Stack,
+    hyprland: Hyprland,
+    per_class: bool,
+    resolver: IconResolver,
+    // The windows behind each icon served so far, by the icon's path. Icons are
+    // kept once served, and only hidden while there are no windows behind them.
+    icons: Mutex<HashMap<String, Arc<MinimizedWindows>>>,
+    registered: Mutex<HashSet<String>>,
+}
+
+impl AggregateDbus {
+    pub fn new(
+        connection: Arc<Connection>,
+        config: Config,
+        stack: Stack,
+        hyprland: Hyprland,
+        per_class: bool,
+    ) -> Self {
+        AggregateDbus {
+            connection,
+            resolver: IconResolver::new(config.icon_overrides.clone().unwrap()),
+            config,
+            stack,
+            hyprland,
+            per_class,
+            icons: Mutex::new(HashMap::new()),
+            registered: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// The path of the icon standing for a window of `class`.
+    fn icon_path(&self, class: &str) -> String {
+        if self.per_class {
+            format!("{}/class/{}", aggregate::ITEM_PATH, path_element(class))
+        } else {
+            aggregate::ITEM_PATH.to_string()
+        }
+    }
+}
+
+/// The menu path of an icon, served next to it.
+fn icon_menu_path(icon_path: &str) -> String {
+    icon_path.replacen(aggregate::ITEM_PATH, aggregate::MENU_PATH, 1)
+}
+
+/// Escapes a string into a single D-Bus object path element, which only allows
+/// ASCII letters, digits and underscores.
+fn path_element(value: &str) -> String {
+    if value.is_empty() {
+        return "_".to_string();
+    }
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => (byte as char).to_string(),
+            _ => format!("_{byte:02x}"),
+        })
+        .collect()
+}
+
+#[async_trait]
+impl DbusConnection for AggregateDbus {
+    async fn setup(
+        &self,
+        window_info: &WindowInfo,
+        exit_notify: Arc<Notify>,
+        _hyprland: &Hyprland,
+    ) -> Result<Option<(Arc<Connection>, String)>> {
+        let icon_path = self.icon_path(&window_info.class);
+        let menu_path = icon_menu_path(&icon_path);
+        let windows = {
+            let mut icons = self.icons.lock().unwrap();
+            Arc::clone(icons.entry(icon_path.clone()).or_insert_with(|| {
+                let windows = MinimizedWindows::new(
+                    self.config.clone(),
+                    self.stack.clone(),
+                    self.hyprland.clone(),
+                );
+                Arc::new(if self.per_class {
+                    windows.with_class(&window_info.class)
+                } else {
+                    windows
+                })
+            }))
+        };
+
+        // The icon is only built the first time, as it then stays served.
+        let object_server = self.connection.object_server();
+        let served = object_server
+            .interface::<_, AggregateItem>(icon_path.as_str())
+            .await
+            .is_ok();
+        if !served {
+            let (icon_name, label) = if self.per_class {
+                let icon_name = self.resolver.resolve(&window_info.class);
+                (icon_name, window_info.class.as_str())
+            } else {
+                (aggregate::ICON_NAME.to_string(), "Minimized windows")
+            };
+            let item = AggregateItem::new(Arc::clone(&windows))
+                .with_icon_pixmap(self.resolver.pixmaps(&icon_name, label).await)
+                .with_icon_name(icon_name)
+                .with_menu_path(OwnedObjectPath::try_from(menu_path.as_str())?);
+            // Serving an icon that another window got served meanwhile leaves it as
+            // it is. The menu goes first, so a served icon always has its menu.
+            object_server
+                .at(menu_path.as_str(), AggregateMenu::new(Arc::clone(&windows)))
+                .await?;
+            object_server.at(icon_path.as_str(), item).await?;
+        }
+
+        windows.insert(&window_info.address, exit_notify);
+        aggregate::refresh(&self.connection, &icon_path, &menu_path).await?;
+
+        let key = format!("{icon_path}/{}", window_info.address);
+        Ok(Some((Arc::clone(&self.connection), key)))
+    }
+
+    async fn register(&self, connection: &Arc<Connection>, key: &str) -> Result<()> {
+        let (icon_path, _) = key.rsplit_once('/').unwrap_or((key, ""));
+        if self.registered.lock().unwrap().contains(icon_path) {
+            return Ok(());
+        }
+        register_with_watcher(connection, icon_path).await?;
+        self.registered
+            .lock()
+            .unwrap()
+            .insert(icon_path.to_string());
+        Ok(())
+    }
+
+    async fn teardown(&self, connection: &Arc<Connection>, key: &str) -> Result<()> {
+        let (icon_path, address) = key.rsplit_once('/').unwrap_or((key, ""));
+        let windows = self.icons.lock().unwrap().get(icon_path).cloned();
+        if let Some(windows) = windows {
+            windows.remove(address);
+        }
+        // With no window left, the icon goes passive, which hosts hide.
+        aggregate::refresh(connection, icon_path, &icon_menu_path(icon_path)).await
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// A `SharedDbus` over a peer-to-peer connection, so tests can host tray objects
+    /// without a session bus. Registration with a watcher is skipped.
+    pub struct PeerDbus {
+        shared: SharedDbus,
+        // Keeps the other end of the connection alive.
+        _peer: Connection,
+    }
+
+    impl PeerDbus {
+        pub async fn new() -> Self {
+            let (server, client) = tokio::net::UnixStream::pair().unwrap();
+            let guid = zbus::Guid::generate();
+            let (server, client) = tokio::join!(
+                ConnectionBuilder::unix_stream(server)
+                    .server(&guid)
+                    .p2p()
+                    .build(),
+                ConnectionBuilder::unix_stream(client).p2p().build(),
+            );
+            PeerDbus {
+                shared: SharedDbus::new(Arc::new(server.unwrap())),
+                _peer: client.unwrap(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl DbusConnection for PeerDbus {
+        async fn setup(
+            &self,
+            window_info: &WindowInfo,
+            exit_notify: Arc<Notify>,
+            hyprland: &Hyprland,
+        ) -> Result<Option<(Arc<Connection>, String)>> {
+            self.shared.setup(window_info, exit_notify, hyprland).await
+        }
+        async fn register(&self, _connection: &Arc<Connection>, _item_path: &str) -> Result<()> {
+            Ok(())
+        }
+        async fn teardown(&self, connection: &Arc<Connection>, item_path: &str) -> Result<()> {
+            self.shared.teardown(connection, item_path).await
+        }
+    }
+
+    #[test]
+    fn test_class_icons_get_valid_paths() {
+        assert_eq!(path_element("kitty"), "kitty");
+        assert_eq!(path_element("org.gnome.Nautilus"), "org_2egnome_2eNautilus");
+        assert_eq!(path_element(""), "_");
+        assert_eq!(
+            icon_menu_path("/StatusNotifierItem/class/kitty"),
+            "/Menu/class/kitty"
+        );
+        assert!(OwnedObjectPath::try_from(format!("/x/{}", path_element("a-b c"))).is_ok());
+    }
+
+    #[test]
+    fn test_object_paths_are_keyed_by_address() {
+        assert_eq!(
+            item_path("0x55d0c0ffee00").unwrap().as_str(),
+            "/StatusNotifierItem/0x55d0c0ffee00"
+        );
+        assert_eq!(
+            menu_path("0x55d0c0ffee00").unwrap().as_str(),
+            "/Menu/0x55d0c0ffee00"
+        );
+        assert!(item_path("not an address").is_err());
+    }
+}
@@ -0,0 +1,889 @@
+//! A tray icon standing for several minimized windows, every one or those of a class,
+//! with a menu listing them.
+use crate::cli::RestoreTarget;
+use crate::config::Config;
+use crate::hyprland::Hyprland;
+use crate::pixmap::Pixmap;
+use crate::restore::restore_to_outside;
+use crate::stack::{Stack, StackEntry};
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, Value};
+use zbus::{Connection, SignalContext, dbus_interface, fdo};
+
+pub const ITEM_PATH: &str = "/StatusNotifierItem";
+pub const MENU_PATH: &str = "/Menu";
+/// The icon shown when the windows of every class share it.
+pub const ICON_NAME: &str = "window-minimize";
+
+/// The ids of the fixed menu items. Windows get ids from `FIRST_WINDOW_ID` on, a
+/// block of `WINDOW_IDS` each: the window's item, then those of its submenu.
+const RESTORE_LAST: i32 = 1;
+const RESTORE_ALL: i32 = 2;
+const SEPARATOR: i32 = 3;
+const FIRST_WINDOW_ID: i32 = 10;
+const WINDOW_IDS: i32 = 4;
+
+/// The offsets of a window's submenu items from the id of the window's item.
+const OPEN: i32 = 1;
+const OPEN_ON_ORIGINAL: i32 = 2;
+const CLOSE: i32 = 3;
+
+// Type alias to simplify the complex return type of `get_layout`.
+type MenuLayout<'a> = (u32, (i32, HashMap<String, Value<'a>>, Vec<Value<'a>>));
+
+// The properties of a menu item.
+type Properties = HashMap<String, Value<'static>>;
+
+// A menu item, as its id, properties and children.
+type MenuItem = (i32, Properties, Vec<(i32, Properties)>);
+
+// Type alias to simplify the complex return type of `tool_tip`.
+type ToolTip = (String, Vec<(i32, i32, Vec<u8>)>, String, String);
+
+/// The minimized windows behind an icon, shared by its item and its menu.
+pub struct MinimizedWindows {
+    config: Config,
+    // Only the windows of this class, when set.
+    class: Option<String>,
+    stack: Stack,
+    hyprland: Hyprland,
+    // The exit notifier of each window hosted behind the icon.
+    hosted: Mutex<HashMap<String, Arc<Notify>>>,
+    // The address of each listed window at its menu item id, or `None` for a free
+    // id. Hosts refer to items by id, so an id keeps pointing at the same window
+    // while it is listed.
+    ids: Mutex<Vec<Option<String>>>,
+    // The windows listed, as of the last `refresh`.
+    listed: Mutex<Vec<StackEntry>>,
+}
+
+impl MinimizedWindows {
+    pub fn new(config: Config, stack: Stack, hyprland: Hyprland) -> Self {
+        MinimizedWindows {
+            config,
+            class: None,
+            stack,
+            hyprland,
+            hosted: Mutex::new(HashMap::new()),
+            ids: Mutex::new(Vec::new()),
+            listed: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Narrows the windows down to those of a class.
+    pub fn with_class(mut self, class: &str) -> Self {
+        self.class = Some(class.to_string());
+        self
+    }
+
+    /// Hosts a window behind the icon, until `remove` is called.
+    pub fn insert(&self, address: &str, exit_notify: Arc<Notify>) {
+        self.hosted
+            .lock()
+            .unwrap()
+            .insert(address.to_string(), exit_notify);
+    }
+
+    pub fn remove(&self, address: &str) {
+        self.hosted.lock().unwrap().remove(address);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosted.lock().unwrap().is_empty()
+    }
+
+    /// The windows in the minimized workspace, most recently minimized first.
+    async fn entries(&self) -> Vec<StackEntry> {
+        let minimized_workspace = self.config.workspace.as_deref().unwrap();
+        match self
+            .stack
+            .minimized_in(&self.hyprland, minimized_workspace)
+            .await
+        {
+            Ok(entries) => entries
+                .into_iter()
+                .rev()
+                .filter(|entry| {
+                    self.class
+                        .as_ref()
+                        .is_none_or(|class| *class == entry.class)
+                })
+                .collect(),
+            Err(e) => {
+                eprintln!("[Error] Failed to list the minimized windows: {e}");
+                Vec::new()
+            }
+        }
+    }
+
+    /// Lists the minimized windows again, and returns them. The ids of the windows
+    /// no longer listed are freed, for windows listed later.
+    async fn reload(&self) -> Vec<StackEntry> {
+        let entries = self.entries().await;
+        {
+            let mut ids = self.ids.lock().unwrap();
+            for id in ids.iter_mut() {
+                if id
+                    .as_ref()
+                    .is_some_and(|address| !entries.iter().any(|e| e.address == *address))
+                {
+                    *id = None;
+                }
+            }
+            while ids.last().is_some_and(Option::is_none) {
+                ids.pop();
+            }
+        }
+        *self.listed.lock().unwrap() = entries.clone();
+        entries
+    }
+
+    fn id_of(&self, address: &str) -> i32 {
+        let mut ids = self.ids.lock().unwrap();
+        let known = ids
+            .iter()
+            .position(|known| known.as_deref() == Some(address));
+        let index = match known.or_else(|| ids.iter().position(Option::is_none)) {
+            Some(index) => {
+                ids[index] = Some(address.to_string());
+                index
+            }
+            None => {
+                ids.push(Some(address.to_string()));
+                ids.len() - 1
+            }
+        };
+        FIRST_WINDOW_ID + index as i32 * WINDOW_IDS
+    }
+
+    /// The address of the window a menu item belongs to, and the item's offset from
+    /// the window's own item.
+    fn address_of(&self, id: i32) -> Option<(String, i32)> {
+        let index = usize::try_from((id - FIRST_WINDOW_ID) / WINDOW_IDS).ok()?;
+        let address = self.ids.lock().unwrap().get(index).cloned().flatten()?;
+        Some((address, (id - FIRST_WINDOW_ID) % WINDOW_IDS))
+    }
+
+    /// Lets the host of a window know it left the tray.
+    fn notify_host(&self, address: &str) {
+        if let Some(exit_notify) = self.hosted.lock().unwrap().get(address) {
+            exit_notify.notify_one();
+        }
+    }
+
+    /// Restores a window to a target workspace, and lets its host know.
+    async fn restore(&self, entry: &StackEntry, target: RestoreTarget) -> Result<()> {
+        restore_to_outside(
+            &self.hyprland,
+            entry,
+            target,
+            self.config.workspace.as_deref().unwrap(),
+        )
+        .await?;
+        self.notify_host(&entry.address);
+        Ok(())
+    }
+
+    async fn restore_last(&self) -> Result<()> {
+        match self.entries().await.first() {
+            Some(entry) => self.restore(entry, self.config.restore_to.unwrap()).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Restores every window, even past those that fail to, then reports the failures.
+    async fn restore_all(&self) -> Result<()> {
+        let mut failed = Vec::new();
+        for entry in self.entries().await {
+            if let Err(e) = self.restore(&entry, self.config.restore_to.unwrap()).await {
+                eprintln!("[Error] Failed to restore window {}: {e}", entry.address);
+                failed.push(entry.address);
+            }
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("Failed to restore windows {}.", failed.join(", ")))
+        }
+    }
+
+    async fn restore_address(&self, address: &str, target: RestoreTarget) -> Result<()> {
+        let entries = self.entries().await;
+        match entries.iter().find(|entry| entry.address == address) {
+            Some(entry) => self.restore(entry, target).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn close(&self, address: &str) -> Result<()> {
+        self.hyprland
+            .dispatch(&format!("closewindow address:{address}"))
+            .await?;
+        self.notify_host(address);
+        Ok(())
+    }
+}
+
+pub struct AggregateItem {
+    windows: Arc<MinimizedWindows>,
+    icon_name: String,
+    icon_pixmap: Vec<Pixmap>,
+    menu_path: OwnedObjectPath,
+    status: &'static str,
+}
+
+impl AggregateItem {
+    pub fn new(windows: Arc<MinimizedWindows>) -> Self {
+        AggregateItem {
+            windows,
+            icon_name: ICON_NAME.to_string(),
+            icon_pixmap: Vec::new(),
+            menu_path: ObjectPath::from_static_str_unchecked(MENU_PATH).into(),
+            status: "Passive",
+        }
+    }
+
+    /// Shows an icon other than the generic one.
+    pub fn with_icon_name(mut self, icon_name: String) -> Self {
+        self.icon_name = icon_name;
+        self
+    }
+
+    /// Also shows the icon as pixmaps, for hosts that cannot find it by name.
+    pub fn with_icon_pixmap(mut self, icon_pixmap: Vec<Pixmap>) -> Self {
+        self.icon_pixmap = icon_pixmap;
+        self
+    }
+
+    /// Points the item at a menu served somewhere other than `/Menu`.
+    pub fn with_menu_path(mut self, menu_path: OwnedObjectPath) -> Self {
+        self.menu_path = menu_path;
+        self
+    }
+}
+
+#[dbus_interface(name = "org.kde.StatusNotifierItem")]
+impl AggregateItem {
+    #[dbus_interface(property)]
+    fn category(&self) -> &str {
+        "ApplicationStatus"
+    }
+    #[dbus_interface(property)]
+    fn id(&self) -> &str {
+        self.windows
+            .class
+            .as_deref()
+            .unwrap_or("hyprland-minimizer")
+    }
+    #[dbus_interface(property)]
+    fn title(&self) -> &str {
+        self.windows.class.as_deref().unwrap_or("Minimized windows")
+    }
+    #[dbus_interface(property)]
+    fn status(&self) -> &str {
+        self.status
+    }
+    #[dbus_interface(property)]
+    fn icon_name(&self) -> &str {
+        &self.icon_name
+    }
+    #[dbus_interface(property)]
+    fn icon_pixmap(&self) -> Vec<Pixmap> {
+        self.icon_pixmap.clone()
+    }
+    #[dbus_interface(property)]
+    fn tool_tip(&self) -> ToolTip {
+        // Counted from the windows the menu lists, so that both agree.
+        let count = self.windows.listed.lock().unwrap().len();
+        (
+            String::new(),
+            Vec::new(),
+            format!("{count} minimized window(s)"),
+            String::new(),
+        )
+    }
+    #[dbus_interface(property)]
+    fn item_is_menu(&self) -> bool {
+        false
+    }
+    #[dbus_interface(property)]
+    fn menu(&self) -> ObjectPath<'_> {
+        self.menu_path.as_ref()
+    }
+
+    /// Restores the most recently minimized window.
+    async fn activate(&self, _x: i32, _y: i32) {
+        if let Err(e) = self.windows.restore_last().await {
+            eprintln!("[Error] Failed to restore the last minimized window: {e}");
+        }
+    }
+
+    #[dbus_interface(signal)]
+    pub async fn new_status(ctxt: &SignalContext<'_>, status: &str) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    pub async fn new_tool_tip(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+}
+
+pub struct AggregateMenu {
+    windows: Arc<MinimizedWindows>,
+    revision: u32,
+    // The menu items, as built by the last `refresh`.
+    items: Vec<MenuItem>,
+}
+
+impl AggregateMenu {
+    pub fn new(windows: Arc<MinimizedWindows>) -> Self {
+        AggregateMenu {
+            windows,
+            revision: 1,
+            items: Vec::new(),
+        }
+    }
+
+    /// Builds the menu items of `entries` under a new revision, and returns it.
+    fn update(&mut self, entries: &[StackEntry]) -> u32 {
+        self.items = self.items(entries);
+        self.revision += 1;
+        self.revision
+    }
+
+    /// The menu items, in display order. The icon of a class lists its windows as
+    /// submenus, the icon of every window lists them as items that restore them.
+    fn items(&self, entries: &[StackEntry]) -> Vec<MenuItem> {
+        let mut items = Vec::new();
+        if self.windows.class.is_none() {
+            let enabled = !entries.is_empty();
+            items.push((
+                RESTORE_LAST,
+                properties("Restore last", enabled),
+                Vec::new(),
+            ));
+            items.push((RESTORE_ALL, properties("Restore all", enabled), Vec::new()));
+            let mut separator = HashMap::new();
+            separator.insert("type".to_string(), Value::from("separator"));
+            items.push((SEPARATOR, separator, Vec::new()));
+        }
+        for entry in entries {
+            let id = self.windows.id_of(&entry.address);
+            if self.windows.class.is_none() {
+                let label = format!("{} ({})", entry.title, entry.class);
+                items.push((id, properties(&label, true), Vec::new()));
+                continue;
+            }
+            // Names are more telling than ids, when workspaces have one.
+            let original = if entry.workspace_name.is_empty() {
+                entry.workspace_id.to_string()
+            } else {
+                entry.workspace_name.clone()
+            };
+            let mut props = properties(&entry.title, true);
+            props.insert("children-display".to_string(), Value::from("submenu"));
+            let submenu = vec![
+                (id + OPEN, properties("Open", true)),
+                (
+                    id + OPEN_ON_ORIGINAL,
+                    properties(&format!("Open on original workspace ({original})"), true),
+                ),
+                (id + CLOSE, properties("Close", true)),
+            ];
+            items.push((id, props, submenu));
+        }
+        items
+    }
+
+    /// The layout of the item `id` and of its children down to `depth` levels, every
+    /// level when `depth` is negative, or `None` when there is no such item.
+    fn layout(&self, id: i32, depth: i32) -> Option<(i32, Properties, Vec<Value<'static>>)> {
+        let leaf = |(id, props): &(i32, Properties)| {
+            Value::from((*id, props.clone(), Vec::<Value>::new()))
+        };
+        if id == 0 {
+            let children = match depth {
+                0 => Vec::new(),
+                _ => self
+                    .items
+                    .iter()
+                    .map(|(id, props, submenu)| {
+                        let submenu = match depth {
+                            1 => Vec::new(),
+                            _ => submenu.iter().map(leaf).collect(),
+                        };
+                        Value::from((*id, props.clone(), submenu))
+                    })
+                    .collect(),
+            };
+            let mut root_props = HashMap::new();
+            root_props.insert("children-display".to_string(), Value::from("submenu"));
+            return Some((0, root_props, children));
+        }
+        self.items.iter().find_map(|(item_id, props, submenu)| {
+            if *item_id == id {
+                let children = match depth {
+                    0 => Vec::new(),
+                    _ => submenu.iter().map(leaf).collect(),
+                };
+                return Some((id, props.clone(), children));
+            }
+            let (_, props) = submenu.iter().find(|(child_id, _)| *child_id == id)?;
+            Some((id, props.clone(), Vec::new()))
+        })
+    }
+}
+
+fn properties(label: &str, enabled: bool) -> Properties {
+    let mut props = HashMap::new();
+    props.insert("type".to_string(), Value::from("standard"));
+    props.insert("label".to_string(), Value::from(label.to_string()));
+    props.insert("enabled".to_string(), Value::from(enabled));
+    props.insert("visible".to_string(), Value::from(true));
+    props
+}
+
+#[dbus_interface(name = "com.canonical.dbusmenu")]
+impl AggregateMenu {
+    fn get_layout(
+        &self,
+        parent_id: i32,
+        recursion_depth: i32,
+        _property_names: Vec<String>,
+    ) -> fdo::Result<MenuLayout<'static>> {
+        match self.layout(parent_id, recursion_depth) {
+            Some(layout) => Ok((self.revision, layout)),
+            None => Err(fdo::Error::InvalidArgs(format!(
+                "There is no menu item {parent_id}."
+            ))),
+        }
+    }
+
+    fn get_group_properties(
+        &self,
+        ids: Vec<i32>,
+        _property_names: Vec<String>,
+    ) -> Vec<(i32, Properties)> {
+        self.items
+            .iter()
+            .flat_map(|(id, props, children)| {
+                std::iter::once((*id, props.clone())).chain(children.iter().cloned())
+            })
+            .filter(|(id, _)| ids.contains(id))
+            .collect()
+    }
+
+    /// Handles a batch of click events.
+    async fn event_group(&self, events: Vec<(i32, String, Value<'_>, u32)>) {
+        for (id, event_id, data, timestamp) in events {
+            self.event(id, &event_id, data, timestamp).await;
+        }
+    }
+
+    /// Handles a single click event on a menu item.
+    async fn event(&self, id: i32, event_id: &str, _data: Value<'_>, _timestamp: u32) {
+        if event_id != "clicked" {
+            return;
+        }
+
+        let res = match id {
+            RESTORE_LAST => self.windows.restore_last().await,
+            RESTORE_ALL => self.windows.restore_all().await,
+            id => match self.windows.address_of(id) {
+                Some((address, 0 | OPEN)) => {
+                    let target = self.windows.config.restore_to.unwrap();
+                    self.windows.restore_address(&address, target).await
+                }
+                Some((address, OPEN_ON_ORIGINAL)) => {
+                    self.windows
+                        .restore_address(&address, RestoreTarget::Original)
+                        .await
+                }
+                Some((address, CLOSE)) => self.windows.close(&address).await,
+                _ => return,
+            },
+        };
+
+        if let Err(e) = res {
+            eprintln!("[Error] Failed to execute hyprctl dispatch from menu: {e}");
+        }
+    }
+
+    fn about_to_show_group(&self, _ids: Vec<i32>) -> (Vec<i32>, Vec<i32>) {
+        (vec![], vec![])
+    }
+
+    fn about_to_show(&self, _id: i32) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn version(&self) -> u32 {
+        3
+    }
+
+    #[dbus_interface(property)]
+    fn text_direction(&self) -> &str {
+        "ltr"
+    }
+
+    #[dbus_interface(property)]
+    fn status(&self) -> &str {
+        "normal"
+    }
+
+    #[dbus_interface(signal)]
+    pub async fn layout_updated(
+        ctxt: &SignalContext<'_>,
+        revision: u32,
+        parent: i32,
+    ) -> zbus::Result<()>;
+}
+
+/// Tells hosts the list of windows behind an icon changed: its menu is built again
+/// under a new revision, and the icon only shows while there are windows behind it.
+pub async fn refresh(connection: &Connection, item_path: &str, menu_path: &str) -> Result<()> {
+    let object_server = connection.object_server();
+
+    let menu = object_server
+        .interface::<_, AggregateMenu>(menu_path)
+        .await?;
+    // Listed before locking the menu, which would otherwise stall its callers.
+    let windows = Arc::clone(&menu.get().await.windows);
+    let entries = windows.reload().await;
+    let revision = menu.get_mut().await.update(&entries);
+    AggregateMenu::layout_updated(menu.signal_context(), revision, 0).await?;
+
+    let item = object_server
+        .interface::<_, AggregateItem>(item_path)
+        .await?;
+    AggregateItem::new_tool_tip(item.signal_context()).await?;
+    let status = if item.get().await.windows.is_empty() {
+        "Passive"
+    } else {
+        "Active"
+    };
+    let changed = {
+        let mut item = item.get_mut().await;
+        std::mem::replace(&mut item.status, status) != status
+    };
+    if changed {
+        AggregateItem::new_status(item.signal_context(), status).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hyprland;
+    use async_trait::async_trait;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{ExitStatus, Output};
+    use std::time::Duration;
+    use tempfile::NamedTempFile;
+    use tokio::time::timeout;
+
+    // --- Mocking Setup ---
+
+    #[derive(Default, Clone)]
+    struct MockExecutor {
+        dispatched_commands: Arc<Mutex<Vec<String>>>,
+        dispatched_batches: Arc<Mutex<Vec<Vec<String>>>>,
+        json_response: String,
+        // Batches naming this address fail.
+        failing_address: Arc<Mutex<Option<String>>>,
+    }
+    #[async_trait]
+    impl hyprland::HyprctlExecutor for MockExecutor {
+        async fn execute_json(&self, _command: &str) -> Result<Output> {
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: self.json_response.as_bytes().to_vec(),
+                stderr: vec![],
+            })
+        }
+        async fn execute_dispatch(&self, command: &str) -> Result<Output> {
+            self.dispatched_commands
+                .lock()
+                .unwrap()
+                .push(command.to_string());
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: vec![],
+                stderr: vec![],
+            })
+        }
+        async fn execute_batch(&self, commands: &[String]) -> Result<Output> {
+            self.dispatched_batches
+                .lock()
+                .unwrap()
+                .push(commands.to_vec());
+            let failed = self
+                .failing_address
+                .lock()
+                .unwrap()
+                .as_ref()
+                .is_some_and(|address| {
+                    commands
+                        .iter()
+                        .any(|command| command.ends_with(address.as_str()))
+                });
+            Ok(Output {
+                status: ExitStatus::from_raw(if failed { 1 << 8 } else { 0 }),
+                stdout: vec![],
+                stderr: vec![],
+            })
+        }
+    }
+
+    const CLIENTS: &str = r#"[
+        {"address": "0xTERM", "workspace": {"id": -98, "name": "special:minimized"}, "title": "Term", "class": "kitty"},
+        {"address": "0xWEB", "workspace": {"id": -98, "name": "special:minimized"}, "title": "Web", "class": "firefox"}
+    ]"#;
+
+    /// A menu over a stack holding a terminal, then a browser minimized after it.
+    /// With a class, the menu only lists the windows of that class.
+    async fn create_test_menu(
+        stack_file: &NamedTempFile,
+        class: Option<&str>,
+    ) -> (AggregateMenu, Arc<MockExecutor>) {
+        let stack = Stack::new(stack_file.path());
+        for (address, title, class, workspace_id) in [
+            ("0xTERM", "Term", "kitty", 2),
+            ("0xWEB", "Web", "firefox", 3),
+        ] {
+            stack
+                .push(StackEntry {
+                    title: title.to_string(),
+                    class: class.to_string(),
+                    workspace_id,
+                    workspace_name: workspace_id.to_string(),
+                    ..StackEntry::from(address)
+                })
+                .unwrap();
+        }
+        let executor = Arc::new(MockExecutor {
+            json_response: CLIENTS.to_string(),
+            ..Default::default()
+        });
+        let config = Config {
+            restore_to: Some(RestoreTarget::Original),
+            ..Config::default()
+        };
+        let hyprland = Hyprland::new(executor.clone() as Arc<dyn hyprland::HyprctlExecutor>);
+        let mut windows = MinimizedWindows::new(config, stack, hyprland);
+        if let Some(class) = class {
+            windows = windows.with_class(class);
+        }
+        let mut menu = AggregateMenu::new(Arc::new(windows));
+        let entries = menu.windows.reload().await;
+        menu.update(&entries);
+        (menu, executor)
+    }
+
+    fn label(props: &HashMap<String, Value<'_>>) -> String {
+        props
+            .get("label")
+            .map(|label| String::try_from(label.clone()).unwrap())
+            .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn test_layout_lists_windows_most_recent_first() {
+        let stack_file = NamedTempFile::new().unwrap();
+        let (menu, _executor) = create_test_menu(&stack_file, None).await;
+
+        let (revision, (_, _, items)) = menu.get_layout(0, -1, vec![]).unwrap();
+        assert_eq!(revision, 2);
+        assert_eq!(items.len(), 5);
+
+        let labels: Vec<(i32, String)> = menu
+            .get_group_properties(vec![1, 2, 10, 14], vec![])
+            .iter()
+            .map(|(id, props)| (*id, label(props)))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                (1, "Restore last".to_string()),
+                (2, "Restore all".to_string()),
+                (10, "Web (firefox)".to_string()),
+                (14, "Term (kitty)".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clicking_a_window_restores_it_and_notifies_its_host() {
+        let stack_file = NamedTempFile::new().unwrap();
+        let (menu, executor) = create_test_menu(&stack_file, None).await;
+        let notify = Arc::new(Notify::new());
+        menu.windows.insert("0xTERM", Arc::clone(&notify));
+
+        menu.event(14, "clicked", Value::from(0), 0).await;
+
+        assert_eq!(
+            *executor.dispatched_batches.lock().unwrap(),
+            [[
+                "movetoworkspace 2,address:0xTERM",
+                "focuswindow address:0xTERM"
+            ]]
+        );
+        assert!(
+            timeout(Duration::from_millis(10), notify.notified())
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_restore_last_and_restore_all() {
+        let stack_file = NamedTempFile::new().unwrap();
+        let (menu, executor) = create_test_menu(&stack_file, None).await;
+
+        menu.event(RESTORE_LAST, "clicked", Value::from(0), 0).await;
+        assert_eq!(
+            *executor.dispatched_batches.lock().unwrap(),
+            [[
+                "movetoworkspace 3,address:0xWEB",
+                "focuswindow address:0xWEB"
+            ]]
+        );
+
+        executor.dispatched_batches.lock().unwrap().clear();
+        menu.event(RESTORE_ALL, "clicked", Value::from(0), 0).await;
+        assert_eq!(
+            *executor.dispatched_batches.lock().unwrap(),
+            [
+                [
+                    "movetoworkspace 3,address:0xWEB",
+                    "focuswindow address:0xWEB"
+                ],
+                [
+                    "movetoworkspace 2,address:0xTERM",
+                    "focuswindow address:0xTERM"
+                ]
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ids_of_windows_no_longer_listed_are_freed() {
+        let stack_file = NamedTempFile::new().unwrap();
+        let (menu, _executor) = create_test_menu(&stack_file, None).await;
+        assert_eq!(menu.windows.address_of(10), Some(("0xWEB".to_string(), 0)));
+
+        menu.windows.stack.remove("0xWEB").unwrap();
+        menu.windows.reload().await;
+
+        assert_eq!(menu.windows.address_of(10), None);
+        assert_eq!(menu.windows.address_of(14), Some(("0xTERM".to_string(), 0)));
+        assert_eq!(menu.windows.id_of("0xNEW"), 10);
+    }
+
+    #[tokio::test]
+    async fn test_restore_all_goes_on_past_a_failure() {
+        let stack_file = NamedTempFile::new().unwrap();
+        let (menu, executor) = create_test_menu(&stack_file, None).await;
+        *executor.failing_address.lock().unwrap() = Some("0xWEB".to_string());
+
+        let error = menu.windows.restore_all().await.unwrap_err();
+        assert!(error.to_string().contains("0xWEB"));
+        assert!(!error.to_string().contains("0xTERM"));
+        assert_eq!(executor.dispatched_batches.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_class_menu_has_a_submenu_per_window() {
+        let stack_file = NamedTempFile::new().unwrap();
+        let (menu, executor) = create_test_menu(&stack_file, Some("kitty")).await;
+
+        let (_, (_, _, items)) = menu.get_layout(0, -1, vec![]).unwrap();
+        assert_eq!(items.len(), 1);
+        let labels: Vec<(i32, String)> = menu
+            .get_group_properties(vec![10, 11, 12, 13], vec![])
+            .iter()
+            .map(|(id, props)| (*id, label(props)))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                (10, "Term".to_string()),
+                (11, "Open".to_string()),
+                (12, "Open on original workspace (2)".to_string()),
+                (13, "Close".to_string()),
+            ]
+        );
+
+        // Hosts may ask for a single level, or for the submenu of a window only.
+        let (_, (_, _, items)) = menu.get_layout(0, 1, vec![]).unwrap();
+        let (id, _, submenu) = <(i32, Properties, Vec<Value>)>::try_from(items[0].clone()).unwrap();
+        assert_eq!((id, submenu.len()), (10, 0));
+        let (_, (id, props, submenu)) = menu.get_layout(10, -1, vec![]).unwrap();
+        assert_eq!(
+            (id, label(&props), submenu.len()),
+            (10, "Term".to_string(), 3)
+        );
+        let (_, (id, _, submenu)) = menu.get_layout(10 + CLOSE, -1, vec![]).unwrap();
+        assert_eq!((id, submenu.len()), (10 + CLOSE, 0));
+        assert!(menu.get_layout(99, -1, vec![]).is_err());
+
+        let notify = Arc::new(Notify::new());
+        menu.windows.insert("0xTERM", Arc::clone(&notify));
+        menu.event(10 + CLOSE, "clicked", Value::from(0), 0).await;
+        assert_eq!(
+            *executor.dispatched_commands.lock().unwrap(),
+            ["closewindow address:0xTERM"]
+        );
+        assert!(
+            timeout(Duration::from_millis(10), notify.notified())
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_shows_the_icon_while_windows_are_hosted() -> Result<()> {
+        let (server, client) = tokio::net::UnixStream::pair()?;
+        let guid = zbus::Guid::generate();
+        let (server, _client) = tokio::join!(
+            zbus::ConnectionBuilder::unix_stream(server)
+                .server(&guid)
+                .p2p()
+                .build(),
+            zbus::ConnectionBuilder::unix_stream(client).p2p().build(),
+        );
+        let connection = server?;
+        let stack_file = NamedTempFile::new()?;
+        let (menu, _executor) = create_test_menu(&stack_file, None).await;
+        let windows = Arc::clone(&menu.windows);
+        let object_server = connection.object_server();
+        object_server
+            .at(ITEM_PATH, AggregateItem::new(Arc::clone(&windows)))
+            .await?;
+        object_server.at(MENU_PATH, menu).await?;
+
+        let item = object_server
+            .interface::<_, AggregateItem>(ITEM_PATH)
+            .await?;
+        let menu = object_server
+            .interface::<_, AggregateMenu>(MENU_PATH)
+            .await?;
+
+        windows.insert("0xTERM", Arc::new(Notify::new()));
+        refresh(&connection, ITEM_PATH, MENU_PATH).await?;
+        assert_eq!(item.get().await.status, "Active");
+        assert_eq!(menu.get().await.revision, 3);
+        // The tooltip counts the windows the menu lists.
+        assert_eq!(item.get().await.tool_tip().2, "2 minimized window(s)");
+        assert_eq!(menu.get().await.items.len(), 3 + 2);
+
+        windows.remove("0xTERM");
+        refresh(&connection, ITEM_PATH, MENU_PATH).await?;
+        assert_eq!(item.get().await.status, "Passive");
+        assert_eq!(menu.get().await.revision, 4);
+
+        Ok(())
+    }
+}
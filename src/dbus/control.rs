@@ -0,0 +1,434 @@
+//! D-Bus implementation for fr.denischevalier.HyprlandMinimizer, the daemon's control interface.
+use crate::cli::RestoreTarget;
+use crate::daemon::{Daemon, DaemonEvent};
+use crate::exit;
+use clap::ValueEnum;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use zbus::{Connection, DBusError, SignalContext, dbus_interface, dbus_proxy};
+
+/// The well-known name owned by the daemon.
+pub const BUS_NAME: &str = "fr.denischevalier.HyprlandMinimizer";
+/// The path the control interface is served at.
+pub const OBJECT_PATH: &str = "/fr/denischevalier/HyprlandMinimizer";
+
+/// A minimized window as returned by `List`: address, title, class and original workspace id.
+pub type ListedWindow = (String, String, String, i32);
+
+pub struct ControlInterface {
+    daemon: Arc<Daemon>,
+}
+
+impl ControlInterface {
+    pub fn new(daemon: Arc<Daemon>) -> Self {
+        ControlInterface { daemon }
+    }
+}
+
+/// The errors of the control interface. Failures with their own exit code get their
+/// own name, so that a request forwarded to the daemon exits like a local one.
+#[derive(DBusError, Debug)]
+#[dbus_error(prefix = "fr.denischevalier.HyprlandMinimizer.Error")]
+pub enum ControlError {
+    #[dbus_error(zbus_error)]
+    ZBus(zbus::Error),
+    Failed(String),
+    InvalidArgs(String),
+    Unreachable(String),
+    NoActiveWindow(String),
+    WindowNotFound(String),
+    StackEmpty(String),
+    NoTrayWatcher(String),
+}
+
+fn failed(e: anyhow::Error) -> ControlError {
+    let message = format!("{e:#}");
+    match exit::code(&e) {
+        exit::UNREACHABLE => ControlError::Unreachable(message),
+        exit::NO_ACTIVE_WINDOW => ControlError::NoActiveWindow(message),
+        exit::WINDOW_NOT_FOUND => ControlError::WindowNotFound(message),
+        exit::STACK_EMPTY => ControlError::StackEmpty(message),
+        exit::NO_TRAY_WATCHER => ControlError::NoTrayWatcher(message),
+        _ => ControlError::Failed(message),
+    }
+}
+
+#[dbus_interface(name = "fr.denischevalier.HyprlandMinimizer")]
+impl ControlInterface {
+    /// Minimizes the window with the given address, or the active one if it is empty.
+    async fn minimize(&self, address: String) -> Result<(), ControlError> {
+        let address = Some(address.as_str()).filter(|a| !a.is_empty());
+        Daemon::minimize(&self.daemon, address)
+            .await
+            .map_err(failed)
+    }
+
+    /// Restores a minimized window to `target`, one of the `restore_to` values
+    /// ("active", "original", "focused-monitor", "cursor-monitor" or "original-monitor"),
+    /// or to the configured target if it is empty.
+    async fn restore(&self, address: String, target: String) -> Result<(), ControlError> {
+        let target = match target.as_str() {
+            "" => None,
+            target => Some(
+                RestoreTarget::from_str(target, true)
+                    .map_err(|_| ControlError::InvalidArgs(format!("Unknown target '{target}'")))?,
+            ),
+        };
+        self.daemon.restore(&address, target).await.map_err(failed)
+    }
+
+    /// Restores the last minimized window.
+    async fn restore_last(&self) -> Result<(), ControlError> {
+        self.daemon.restore_last().await.map_err(failed)
+    }
+
+    /// Lists the minimized windows, most recently minimized last.
+    async fn list(&self) -> Result<Vec<ListedWindow>, ControlError> {
+        let windows = self.daemon.list().await.map_err(failed)?;
+        Ok(windows
+            .into_iter()
+            .map(|w| (w.address, w.title, w.class, w.workspace_id))
+            .collect())
+    }
+
+    /// Closes a minimized window.
+    async fn close(&self, address: String) -> Result<(), ControlError> {
+        self.daemon.close(&address).await.map_err(failed)
+    }
+
+    /// The signature of the Hyprland instance the daemon serves, or empty if unknown.
+    #[dbus_interface(property)]
+    fn instance(&self) -> String {
+        self.daemon.instance().unwrap_or_default().to_string()
+    }
+
+    #[dbus_interface(signal)]
+    async fn window_minimized(
+        ctxt: &SignalContext<'_>,
+        address: &str,
+        title: &str,
+        class: &str,
+    ) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn window_restored(ctxt: &SignalContext<'_>, address: &str) -> zbus::Result<()>;
+}
+
+/// Re-emits the daemon's events as D-Bus signals on the control interface.
+pub async fn emit_signals(
+    connection: Arc<Connection>,
+    mut events: broadcast::Receiver<DaemonEvent>,
+) {
+    let Ok(ctxt) = SignalContext::new(&connection, OBJECT_PATH) else {
+        return;
+    };
+    loop {
+        let result = match events.recv().await {
+            Ok(DaemonEvent::Minimized { window }) => {
+                ControlInterface::window_minimized(
+                    &ctxt,
+                    &window.address,
+                    &window.title,
+                    &window.class,
+                )
+                .await
+            }
+            Ok(DaemonEvent::Restored { address }) => {
+                ControlInterface::window_restored(&ctxt, &address).await
+            }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+        if let Err(e) = result {
+            eprintln!("[Error] Failed to emit D-Bus signal: {e}");
+        }
+    }
+}
+
+/// Client side of the control interface, used to forward requests to a running daemon.
+#[dbus_proxy(
+    interface = "fr.denischevalier.HyprlandMinimizer",
+    default_service = "fr.denischevalier.HyprlandMinimizer",
+    default_path = "/fr/denischevalier/HyprlandMinimizer",
+    gen_blocking = false
+)]
+trait Control {
+    fn minimize(&self, address: &str) -> Result<(), ControlError>;
+    fn restore(&self, address: &str, target: &str) -> Result<(), ControlError>;
+    fn restore_last(&self) -> Result<(), ControlError>;
+    fn list(&self) -> Result<Vec<ListedWindow>, ControlError>;
+    fn close(&self, address: &str) -> Result<(), ControlError>;
+
+    #[dbus_proxy(property)]
+    fn instance(&self) -> zbus::Result<String>;
+
+    #[dbus_proxy(signal)]
+    fn window_minimized(&self, address: &str, title: &str, class: &str) -> zbus::Result<()>;
+
+    #[dbus_proxy(signal)]
+    fn window_restored(&self, address: &str) -> zbus::Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::daemon::SharedDbus;
+    use crate::hyprland::{self, Hyprland};
+    use crate::minimize::register_with_watcher;
+    use crate::stack::Stack;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use futures_util::stream::StreamExt;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{Child, Command, ExitStatus, Output, Stdio};
+    use std::sync::Mutex;
+    use tempfile::NamedTempFile;
+    use tokio::time::{Duration, timeout};
+    use zbus::ConnectionBuilder;
+
+    // --- Mocking Setup ---
+
+    /// Answers each json command with a configurable reply, defaulting to `[]`. Like
+    /// Hyprland, `clients` reports the window as minimized once it has been moved to
+    /// the minimized workspace.
+    #[derive(Default, Clone)]
+    struct MockExecutor {
+        dispatched_commands: Arc<Mutex<Vec<String>>>,
+        dispatched_batches: Arc<Mutex<Vec<Vec<String>>>>,
+        json_responses: Arc<Mutex<HashMap<String, String>>>,
+    }
+    impl MockExecutor {
+        fn set_json_response(&self, command: &str, json: &str) {
+            self.json_responses
+                .lock()
+                .unwrap()
+                .insert(command.to_string(), json.to_string());
+        }
+        fn dispatched_batches(&self) -> Vec<Vec<String>> {
+            self.dispatched_batches.lock().unwrap().clone()
+        }
+        fn apply(&self, command: &str) {
+            if command.starts_with("movetoworkspacesilent special:minimized,") {
+                self.set_json_response("clients", MINIMIZED_CLIENTS);
+            }
+        }
+    }
+    #[async_trait]
+    impl hyprland::HyprctlExecutor for MockExecutor {
+        async fn execute_json(&self, command: &str) -> Result<Output> {
+            let response = self
+                .json_responses
+                .lock()
+                .unwrap()
+                .get(command)
+                .cloned()
+                .unwrap_or_else(|| "[]".to_string());
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: response.into_bytes(),
+                stderr: vec![],
+            })
+        }
+        async fn execute_dispatch(&self, command: &str) -> Result<Output> {
+            self.apply(command);
+            self.dispatched_commands
+                .lock()
+                .unwrap()
+                .push(command.to_string());
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: vec![],
+                stderr: vec![],
+            })
+        }
+        async fn execute_batch(&self, commands: &[String]) -> Result<Output> {
+            commands.iter().for_each(|command| self.apply(command));
+            self.dispatched_batches
+                .lock()
+                .unwrap()
+                .push(commands.to_vec());
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: vec![],
+                stderr: vec![],
+            })
+        }
+    }
+
+    /// A private `dbus-daemon --session`, killed when dropped.
+    struct PrivateBus {
+        process: Child,
+        address: String,
+    }
+
+    impl PrivateBus {
+        /// Starts the bus, or returns `None` when dbus-daemon is not installed.
+        fn start() -> Option<Self> {
+            let mut process = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            BufReader::new(process.stdout.take()?)
+                .read_line(&mut address)
+                .ok()?;
+            Some(PrivateBus {
+                process,
+                address: address.trim().to_string(),
+            })
+        }
+
+        async fn connect(&self) -> Connection {
+            ConnectionBuilder::address(self.address.as_str())
+                .unwrap()
+                .build()
+                .await
+                .unwrap()
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.process.kill();
+            let _ = self.process.wait();
+        }
+    }
+
+    /// Stands in for the tray's org.kde.StatusNotifierWatcher.
+    #[derive(Default, Clone)]
+    struct FakeWatcher {
+        registered: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[dbus_interface(name = "org.kde.StatusNotifierWatcher")]
+    impl FakeWatcher {
+        fn register_status_notifier_item(&self, service: String) {
+            self.registered.lock().unwrap().push(service);
+        }
+    }
+
+    const CLIENTS: &str = r#"[{"address": "0xDBUS", "workspace": {"id": 3, "name": "3"}, "title": "Editor", "class": "code"}]"#;
+    const MINIMIZED_CLIENTS: &str = r#"[{"address": "0xDBUS", "workspace": {"id": -98, "name": "special:minimized"}, "title": "Editor", "class": "code"}]"#;
+
+    #[tokio::test]
+    async fn test_control_interface_on_private_bus() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon is not available, skipping.");
+            return;
+        };
+
+        // A tray watcher, as provided by Waybar and friends.
+        let watcher = FakeWatcher::default();
+        let _watcher_connection = ConnectionBuilder::address(bus.address.as_str())
+            .unwrap()
+            .name("org.kde.StatusNotifierWatcher")
+            .unwrap()
+            .serve_at("/StatusNotifierWatcher", watcher.clone())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        // The daemon, with its tray objects and control interface on one connection.
+        let executor = MockExecutor::default();
+        executor.set_json_response("clients", CLIENTS);
+        let stack_file = NamedTempFile::new().unwrap();
+        let daemon_connection = Arc::new(bus.connect().await);
+        let daemon = Daemon::new(
+            Config {
+                poll_interval_ms: Some(20),
+                ..Config::default()
+            },
+            Stack::new(stack_file.path()),
+            Hyprland::new(Arc::new(executor.clone())).with_instance(Some("abc_1_2".to_string())),
+            Arc::new(SharedDbus::new(Arc::clone(&daemon_connection))),
+        );
+        Daemon::serve_dbus(&daemon, &daemon_connection)
+            .await
+            .unwrap();
+
+        // A client, as busctl or gdbus would be.
+        let client_connection = bus.connect().await;
+        let proxy = ControlProxy::new(&client_connection).await.unwrap();
+        let mut minimized = proxy.receive_window_minimized().await.unwrap();
+        let mut restored = proxy.receive_window_restored().await.unwrap();
+
+        assert_eq!(proxy.instance().await.unwrap(), "abc_1_2");
+        proxy.minimize("0xDBUS").await.unwrap();
+
+        let signal = timeout(Duration::from_secs(2), minimized.next())
+            .await
+            .unwrap()
+            .unwrap();
+        let args = signal.args().unwrap();
+        assert_eq!(
+            (args.address(), args.title(), args.class()),
+            (&"0xDBUS", &"Editor", &"code")
+        );
+
+        assert_eq!(
+            proxy.list().await.unwrap(),
+            vec![(
+                "0xDBUS".to_string(),
+                "Editor".to_string(),
+                "code".to_string(),
+                3
+            )]
+        );
+
+        // The tray icon is served next to the control interface and registered by path.
+        timeout(Duration::from_secs(2), async {
+            while watcher.registered.lock().unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            watcher.registered.lock().unwrap().as_slice(),
+            ["/StatusNotifierItem/0xDBUS"]
+        );
+
+        assert!(proxy.restore("0xDBUS", "sideways").await.is_err());
+        proxy.restore("0xDBUS", "original").await.unwrap();
+        assert!(executor.dispatched_batches().contains(&vec![
+            "movetoworkspace 3,address:0xDBUS".to_string(),
+            "focuswindow address:0xDBUS".to_string()
+        ]));
+
+        // Once Hyprland reports the window as gone, the daemon announces it.
+        executor.set_json_response("clients", "[]");
+        let signal = timeout(Duration::from_secs(2), restored.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(signal.args().unwrap().address(), &"0xDBUS");
+
+        assert!(proxy.close("0xDBUS").await.is_err());
+
+        // Failures keep their kind across the bus, so clients exit with the right code.
+        assert!(matches!(
+            proxy.minimize("0xMISSING").await,
+            Err(ControlError::WindowNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_registering_without_a_tray_watcher() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon is not available, skipping.");
+            return;
+        };
+        let connection = Arc::new(bus.connect().await);
+
+        let error = register_with_watcher(&connection, "/StatusNotifierItem/0x1")
+            .await
+            .unwrap_err();
+
+        assert_eq!(exit::code(&error), exit::NO_TRAY_WATCHER);
+    }
+}
@@ -1,5 +1,8 @@
 //! D-Bus implementation for com.canonical.dbusmenu.
-use crate::hyprland::{Hyprland, WindowInfo, Workspace};
+use crate::cli::RestoreTarget;
+use crate::hyprland::{Hyprland, WindowInfo};
+use crate::restore::restore_to;
+use crate::stack::StackEntry;
 use anyhow::Result;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -13,6 +16,10 @@ pub struct DbusMenu {
     hyprland: Hyprland,
 }
 
+/// The ids of the menu items, in display order. Ids never change meaning, as hosts
+/// refer to items by id.
+const MENU_ITEMS: [i32; 6] = [1, 2, 4, 5, 6, 3];
+
 // Type alias to simplify the complex return type of `get_layout`.
 // The values created are all owned, so we can use the 'static lifetime.
 type MenuLayout<'a> = (u32, (i32, HashMap<String, Value<'a>>, Vec<Value<'a>>));
@@ -26,28 +33,37 @@ impl DbusMenu {
         }
     }
 
-    fn handle_open_on_active(&self) -> Result<()> {
-        let active_workspace = self.hyprland.exec::<Workspace>("activeworkspace")?;
-        self.hyprland.dispatch(&format!(
-            "movetoworkspace {},address:{}",
-            active_workspace.id, self.window_info.address
-        ))?;
-        self.hyprland
-            .dispatch(&format!("focuswindow address:{}", self.window_info.address))
+    async fn handle_open(&self, target: RestoreTarget) -> Result<()> {
+        restore_to(
+            &self.hyprland,
+            &StackEntry::from_window(&self.window_info),
+            target,
+        )
+        .await
     }
 
-    fn handle_open_on_original(&self) -> Result<()> {
-        self.hyprland.dispatch(&format!(
-            "movetoworkspace {},address:{}",
-            self.window_info.workspace.id, self.window_info.address
-        ))?;
-        self.hyprland
-            .dispatch(&format!("focuswindow address:{}", self.window_info.address))
+    /// The label of a menu item, or `None` for an unknown id.
+    fn label(&self, id: i32) -> Option<String> {
+        let workspace = &self.window_info.workspace;
+        Some(match id {
+            1 => format!("Open {}", self.window_info.title),
+            // Names are more telling than ids, when workspaces have one.
+            2 if workspace.name.is_empty() => {
+                format!("Open on original workspace ({})", workspace.id)
+            }
+            2 => format!("Open on original workspace ({})", workspace.name),
+            3 => format!("Close {}", self.window_info.title),
+            4 => "Open on focused monitor".to_string(),
+            5 => "Open on monitor under cursor".to_string(),
+            6 => "Open on original monitor".to_string(),
+            _ => return None,
+        })
     }
 
-    fn handle_close(&self) -> Result<()> {
+    async fn handle_close(&self) -> Result<()> {
         self.hyprland
             .dispatch(&format!("closewindow address:{}", self.window_info.address))
+            .await
     }
 }
 
@@ -59,36 +75,19 @@ impl DbusMenu {
         _recursion_depth: i32,
         _property_names: Vec<String>,
     ) -> MenuLayout<'static> {
-        let mut open_props = HashMap::new();
-        open_props.insert("type".to_string(), Value::from("standard"));
-        open_props.insert(
-            "label".to_string(),
-            Value::from(format!("Open {}", self.window_info.title)),
-        );
-        let open_item = Value::from((1i32, open_props, Vec::<Value>::new()));
-
-        let mut last_ws_props = HashMap::new();
-        last_ws_props.insert("type".to_string(), Value::from("standard"));
-        last_ws_props.insert(
-            "label".to_string(),
-            Value::from(format!(
-                "Open on original workspace ({})",
-                self.window_info.workspace.id
-            )),
-        );
-        let last_ws_item = Value::from((2i32, last_ws_props, Vec::<Value>::new()));
-
-        let mut close_props = HashMap::new();
-        close_props.insert("type".to_string(), Value::from("standard"));
-        close_props.insert(
-            "label".to_string(),
-            Value::from(format!("Close {}", self.window_info.title)),
-        );
-        let close_item = Value::from((3i32, close_props, Vec::<Value>::new()));
+        let items = MENU_ITEMS
+            .iter()
+            .filter_map(|&id| {
+                let mut props = HashMap::new();
+                props.insert("type".to_string(), Value::from("standard"));
+                props.insert("label".to_string(), Value::from(self.label(id)?));
+                Some(Value::from((id, props, Vec::<Value>::new())))
+            })
+            .collect();
 
         let mut root_props = HashMap::new();
         root_props.insert("children-display".to_string(), Value::from("submenu"));
-        let root_layout = (0i32, root_props, vec![open_item, last_ws_item, close_item]);
+        let root_layout = (0i32, root_props, items);
         (2u32, root_layout)
     }
 
@@ -96,18 +95,12 @@ impl DbusMenu {
         &self,
         ids: Vec<i32>,
         _property_names: Vec<String>,
-    ) -> Vec<(i32, HashMap<String, Value>)> {
+    ) -> Vec<(i32, HashMap<String, Value<'_>>)> {
         let mut result = Vec::new();
         for id in ids {
             let mut props = HashMap::new();
-            let label = match id {
-                1 => format!("Open {}", self.window_info.title),
-                2 => format!(
-                    "Open on original workspace ({})",
-                    self.window_info.workspace.id
-                ),
-                3 => format!("Close {}", self.window_info.title),
-                _ => continue,
+            let Some(label) = self.label(id) else {
+                continue;
             };
             props.insert("label".to_string(), Value::from(label));
             props.insert("enabled".to_string(), Value::from(true));
@@ -119,22 +112,25 @@ impl DbusMenu {
     }
 
     /// Handles a batch of click events.
-    fn event_group(&self, events: Vec<(i32, String, Value<'_>, u32)>) {
+    async fn event_group(&self, events: Vec<(i32, String, Value<'_>, u32)>) {
         for (id, event_id, data, timestamp) in events {
-            self.event(id, &event_id, data, timestamp);
+            self.event(id, &event_id, data, timestamp).await;
         }
     }
 
     /// Handles a single click event on a menu item.
-    fn event(&self, id: i32, event_id: &str, _data: Value<'_>, _timestamp: u32) {
+    async fn event(&self, id: i32, event_id: &str, _data: Value<'_>, _timestamp: u32) {
         if event_id != "clicked" {
             return;
         }
 
         let res = match id {
-            1 => self.handle_open_on_active(),
-            2 => self.handle_open_on_original(),
-            3 => self.handle_close(),
+            1 => self.handle_open(RestoreTarget::Active).await,
+            2 => self.handle_open(RestoreTarget::Original).await,
+            3 => self.handle_close().await,
+            4 => self.handle_open(RestoreTarget::FocusedMonitor).await,
+            5 => self.handle_open(RestoreTarget::CursorMonitor).await,
+            6 => self.handle_open(RestoreTarget::OriginalMonitor).await,
             _ => return,
         };
 
@@ -172,7 +168,8 @@ impl DbusMenu {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::hyprland;
+    use crate::hyprland::{self, Workspace};
+    use async_trait::async_trait;
     use std::os::unix::process::ExitStatusExt;
     use std::process::{ExitStatus, Output};
     use std::sync::Mutex;
@@ -184,6 +181,7 @@ mod tests {
     #[derive(Default, Clone)]
     struct MockExecutor {
         dispatched_commands: Arc<Mutex<Vec<String>>>,
+        dispatched_batches: Arc<Mutex<Vec<Vec<String>>>>,
         json_responses: Arc<Mutex<Vec<String>>>,
     }
     impl MockExecutor {
@@ -193,9 +191,13 @@ mod tests {
         fn dispatched_commands(&self) -> Vec<String> {
             self.dispatched_commands.lock().unwrap().clone()
         }
+        fn dispatched_batches(&self) -> Vec<Vec<String>> {
+            self.dispatched_batches.lock().unwrap().clone()
+        }
     }
+    #[async_trait]
     impl hyprland::HyprctlExecutor for MockExecutor {
-        fn execute_json(&self, _command: &str) -> Result<Output> {
+        async fn execute_json(&self, _command: &str) -> Result<Output> {
             let response = self
                 .json_responses
                 .lock()
@@ -208,7 +210,7 @@ mod tests {
                 stderr: vec![],
             })
         }
-        fn execute_dispatch(&self, command: &str) -> Result<Output> {
+        async fn execute_dispatch(&self, command: &str) -> Result<Output> {
             self.dispatched_commands
                 .lock()
                 .unwrap()
@@ -219,6 +221,17 @@ mod tests {
                 stderr: vec![],
             })
         }
+        async fn execute_batch(&self, commands: &[String]) -> Result<Output> {
+            self.dispatched_batches
+                .lock()
+                .unwrap()
+                .push(commands.to_vec());
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: vec![],
+                stderr: vec![],
+            })
+        }
     }
 
     // Helper to create a standard DbusMenu for tests.
@@ -228,7 +241,11 @@ mod tests {
             address: "0xTEST".to_string(),
             class: "TestApp".to_string(),
             title: "Test Window".to_string(),
-            workspace: Workspace { id: 1 },
+            workspace: Workspace {
+                id: 1,
+                ..Default::default()
+            },
+            ..Default::default()
         };
         let hyprland = Hyprland::new(executor as Arc<dyn hyprland::HyprctlExecutor>);
         let menu = DbusMenu::new(window_info, Arc::clone(&notify), &hyprland);
@@ -243,12 +260,16 @@ mod tests {
         let (menu, notify) = create_test_menu(mock_executor.clone());
         mock_executor.add_json_response(r#"{"id": 5}"#);
 
-        menu.event(1, "clicked", Value::from(0), 0);
+        menu.event(1, "clicked", Value::from(0), 0).await;
 
-        let dispatched = mock_executor.dispatched_commands();
-        assert_eq!(dispatched.len(), 2);
-        assert_eq!(dispatched[0], "movetoworkspace 5,address:0xTEST");
-        assert_eq!(dispatched[1], "focuswindow address:0xTEST");
+        assert_eq!(
+            mock_executor.dispatched_batches(),
+            [[
+                "movetoworkspace 5,address:0xTEST",
+                "focuswindow address:0xTEST"
+            ]]
+        );
+        assert!(mock_executor.dispatched_commands().is_empty());
 
         assert!(
             timeout(Duration::from_millis(10), notify.notified())
@@ -262,12 +283,16 @@ mod tests {
         let mock_executor = Arc::new(MockExecutor::default());
         let (menu, notify) = create_test_menu(mock_executor.clone());
 
-        menu.event(2, "clicked", Value::from(0), 0);
+        menu.event(2, "clicked", Value::from(0), 0).await;
 
-        let dispatched = mock_executor.dispatched_commands();
-        assert_eq!(dispatched.len(), 2);
-        assert_eq!(dispatched[0], "movetoworkspace 1,address:0xTEST");
-        assert_eq!(dispatched[1], "focuswindow address:0xTEST");
+        assert_eq!(
+            mock_executor.dispatched_batches(),
+            [[
+                "movetoworkspace 1,address:0xTEST",
+                "focuswindow address:0xTEST"
+            ]]
+        );
+        assert!(mock_executor.dispatched_commands().is_empty());
         assert!(
             timeout(Duration::from_millis(10), notify.notified())
                 .await
@@ -275,12 +300,108 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_open_on_original_named_workspace() {
+        let mock_executor = Arc::new(MockExecutor::default());
+        let (mut menu, _notify) = create_test_menu(mock_executor.clone());
+        menu.window_info.workspace = Workspace {
+            id: -1337,
+            name: "code".to_string(),
+            ..Default::default()
+        };
+
+        let labels = menu.get_group_properties(vec![2], vec![]);
+        assert_eq!(
+            labels[0].1["label"],
+            Value::from("Open on original workspace (code)")
+        );
+
+        menu.event(2, "clicked", Value::from(0), 0).await;
+
+        assert_eq!(
+            mock_executor.dispatched_batches(),
+            [[
+                "movetoworkspace name:code,address:0xTEST",
+                "focuswindow address:0xTEST"
+            ]]
+        );
+    }
+
+    const MONITORS: &str = r#"[
+        {"id": 0, "name": "DP-1", "width": 1920, "height": 1080, "x": 0, "y": 0, "scale": 1.0,
+         "activeWorkspace": {"id": 1, "name": "1"}, "focused": true},
+        {"id": 1, "name": "DP-2", "width": 1920, "height": 1080, "x": 1920, "y": 0, "scale": 1.0,
+         "activeWorkspace": {"id": 7, "name": "7"}, "focused": false}
+    ]"#;
+
+    #[tokio::test]
+    async fn test_open_on_monitor_under_cursor() {
+        let mock_executor = Arc::new(MockExecutor::default());
+        let (menu, _notify) = create_test_menu(mock_executor.clone());
+        // Replies are served last first: monitors, then the cursor position.
+        mock_executor.add_json_response(r#"{"x": 2500, "y": 300}"#);
+        mock_executor.add_json_response(MONITORS);
+
+        menu.event(5, "clicked", Value::from(0), 0).await;
+
+        assert_eq!(
+            mock_executor.dispatched_batches(),
+            [[
+                "movetoworkspace 7,address:0xTEST",
+                "focuswindow address:0xTEST"
+            ]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_open_on_unplugged_original_monitor_falls_back_to_focused() {
+        let mock_executor = Arc::new(MockExecutor::default());
+        let (mut menu, _notify) = create_test_menu(mock_executor.clone());
+        menu.window_info.monitor = 2;
+        mock_executor.add_json_response(MONITORS);
+
+        menu.event(6, "clicked", Value::from(0), 0).await;
+
+        assert_eq!(
+            mock_executor.dispatched_batches(),
+            [[
+                "movetoworkspace 1,address:0xTEST",
+                "focuswindow address:0xTEST"
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_layout_lists_every_restore_target() {
+        let (menu, _notify) = create_test_menu(Arc::new(MockExecutor::default()));
+
+        let (_, (_, _, items)) = menu.get_layout(0, -1, vec![]);
+
+        let labels: Vec<String> = menu
+            .get_group_properties(MENU_ITEMS.to_vec(), vec![])
+            .into_iter()
+            .map(|(_, props)| String::try_from(props["label"].clone()).unwrap())
+            .collect();
+        assert_eq!(items.len(), labels.len());
+        assert_eq!(
+            labels,
+            [
+                "Open Test Window",
+                "Open on original workspace (1)",
+                "Open on focused monitor",
+                "Open on monitor under cursor",
+                "Open on original monitor",
+                "Close Test Window"
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn test_event_click_option_3_close_window() {
         let mock_executor = Arc::new(MockExecutor::default());
         let (menu, notify) = create_test_menu(mock_executor.clone());
 
-        menu.event(3, "clicked", Value::from(0), 0);
+        menu.event(3, "clicked", Value::from(0), 0).await;
 
         let dispatched = mock_executor.dispatched_commands();
         assert_eq!(dispatched.len(), 1);
@@ -1,5 +1,7 @@
 //! D-Bus interfaces for StatusNotifierItem and DBusMenu.
 
+pub mod aggregate;
+pub mod control;
 pub mod menu;
 pub mod notifier;
 
@@ -1,15 +1,23 @@
 //! D-Bus implementation for org.kde.StatusNotifierItem.
-use crate::hyprland::{Hyprland, WindowInfo, Workspace};
+use crate::cli::RestoreTarget;
+use crate::hyprland::{Hyprland, WindowInfo};
+use crate::pixmap::Pixmap;
+use crate::restore::restore_to;
+use crate::stack::StackEntry;
 use anyhow::Result;
 use std::sync::Arc;
 use tokio::sync::Notify;
-use zbus::dbus_interface;
-use zbus::zvariant::ObjectPath;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath};
+use zbus::{SignalContext, dbus_interface};
 
 pub struct StatusNotifierItem {
     window_info: WindowInfo,
+    icon_name: String,
+    icon_pixmap: Vec<Pixmap>,
     exit_notify: Arc<Notify>,
     hyprland: Hyprland,
+    menu_path: OwnedObjectPath,
+    status: &'static str,
 }
 
 // Type alias to simplify the complex return type of `tool_tip`.
@@ -18,16 +26,43 @@ type ToolTip = (String, Vec<(i32, i32, Vec<u8>)>, String, String);
 impl StatusNotifierItem {
     pub fn new(window_info: WindowInfo, exit_notify: Arc<Notify>, hyprland: Hyprland) -> Self {
         StatusNotifierItem {
+            icon_name: window_info.class.clone(),
+            icon_pixmap: Vec::new(),
             window_info,
             exit_notify,
             hyprland,
+            menu_path: ObjectPath::from_static_str_unchecked("/Menu").into(),
+            status: "Active",
         }
     }
 
-    /// A helper to wrap D-Bus actions. It executes the provided closure,
+    /// Points the item at a menu served somewhere other than `/Menu`.
+    pub fn with_menu_path(mut self, menu_path: OwnedObjectPath) -> Self {
+        self.menu_path = menu_path;
+        self
+    }
+
+    /// Shows an icon other than the one named after the window class.
+    pub fn with_icon_name(mut self, icon_name: String) -> Self {
+        self.icon_name = icon_name;
+        self
+    }
+
+    /// Also shows the icon as pixmaps, for hosts that cannot find it by name.
+    pub fn with_icon_pixmap(mut self, icon_pixmap: Vec<Pixmap>) -> Self {
+        self.icon_pixmap = icon_pixmap;
+        self
+    }
+
+    /// Marks the item as passive, which hosts hide from the tray.
+    pub fn set_passive(&mut self) {
+        self.status = "Passive";
+    }
+
+    /// A helper to wrap D-Bus actions. It awaits the provided action,
     /// logs any resulting error, and always sends an exit notification.
-    fn handle_action(&self, action: impl FnOnce() -> Result<()>) {
-        if let Err(e) = action() {
+    async fn handle_action(&self, action: impl Future<Output = Result<()>>) {
+        if let Err(e) = action.await {
             eprintln!("[Error] Failed to execute hyprctl dispatch from notifier: {e}");
         }
         self.exit_notify.notify_one();
@@ -50,11 +85,15 @@ impl StatusNotifierItem {
     }
     #[dbus_interface(property)]
     fn status(&self) -> &str {
-        "Active"
+        self.status
     }
     #[dbus_interface(property)]
     fn icon_name(&self) -> &str {
-        &self.window_info.class
+        &self.icon_name
+    }
+    #[dbus_interface(property)]
+    fn icon_pixmap(&self) -> Vec<Pixmap> {
+        self.icon_pixmap.clone()
     }
     #[dbus_interface(property)]
     fn tool_tip(&self) -> ToolTip {
@@ -71,33 +110,39 @@ impl StatusNotifierItem {
     }
     #[dbus_interface(property)]
     fn menu(&self) -> ObjectPath<'_> {
-        ObjectPath::try_from("/Menu").unwrap()
+        self.menu_path.as_ref()
     }
 
-    fn activate(&self, _x: i32, _y: i32) {
-        self.handle_action(|| {
-            let active_workspace = self.hyprland.exec::<Workspace>("activeworkspace")?;
-            self.hyprland.dispatch(&format!(
-                "movetoworkspace {},address:{}",
-                active_workspace.id, self.window_info.address
-            ))?;
-            self.hyprland
-                .dispatch(&format!("focuswindow address:{}", self.window_info.address))
-        });
+    async fn activate(&self, _x: i32, _y: i32) {
+        self.handle_action(async {
+            restore_to(
+                &self.hyprland,
+                &StackEntry::from_window(&self.window_info),
+                RestoreTarget::Active,
+            )
+            .await
+        })
+        .await;
     }
 
-    fn secondary_activate(&self, _x: i32, _y: i32) {
-        self.handle_action(|| {
+    async fn secondary_activate(&self, _x: i32, _y: i32) {
+        self.handle_action(async {
             self.hyprland
                 .dispatch(&format!("closewindow address:{}", self.window_info.address))
-        });
+                .await
+        })
+        .await;
     }
+
+    #[dbus_interface(signal)]
+    pub async fn new_status(ctxt: &SignalContext<'_>, status: &str) -> zbus::Result<()>;
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::hyprland;
+    use crate::hyprland::{self, Workspace};
+    use async_trait::async_trait;
     use std::os::unix::process::ExitStatusExt;
     use std::process::{ExitStatus, Output};
     use std::sync::{Arc, Mutex};
@@ -109,22 +154,27 @@ mod tests {
     #[derive(Default, Clone)]
     struct MockExecutor {
         dispatched_commands: Arc<Mutex<Vec<String>>>,
+        dispatched_batches: Arc<Mutex<Vec<Vec<String>>>>,
         json_response: Arc<Mutex<String>>,
     }
     impl MockExecutor {
         fn dispatched_commands(&self) -> Vec<String> {
             self.dispatched_commands.lock().unwrap().clone()
         }
+        fn dispatched_batches(&self) -> Vec<Vec<String>> {
+            self.dispatched_batches.lock().unwrap().clone()
+        }
     }
+    #[async_trait]
     impl hyprland::HyprctlExecutor for MockExecutor {
-        fn execute_json(&self, _command: &str) -> Result<Output, anyhow::Error> {
+        async fn execute_json(&self, _command: &str) -> Result<Output, anyhow::Error> {
             Ok(Output {
                 status: ExitStatus::from_raw(0),
                 stdout: self.json_response.lock().unwrap().as_bytes().to_vec(),
                 stderr: vec![],
             })
         }
-        fn execute_dispatch(&self, command: &str) -> Result<Output, anyhow::Error> {
+        async fn execute_dispatch(&self, command: &str) -> Result<Output, anyhow::Error> {
             self.dispatched_commands
                 .lock()
                 .unwrap()
@@ -135,6 +185,17 @@ mod tests {
                 stderr: vec![],
             })
         }
+        async fn execute_batch(&self, commands: &[String]) -> Result<Output, anyhow::Error> {
+            self.dispatched_batches
+                .lock()
+                .unwrap()
+                .push(commands.to_vec());
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: vec![],
+                stderr: vec![],
+            })
+        }
     }
 
     // Helper to create a standard StatusNotifierItem for tests.
@@ -144,7 +205,11 @@ mod tests {
             address: "0xNOTIFY_TEST".to_string(),
             class: "NotifierApp".to_string(),
             title: "Notifier Window".to_string(),
-            workspace: Workspace { id: 1 },
+            workspace: Workspace {
+                id: 1,
+                ..Default::default()
+            },
+            ..Default::default()
         };
         let hyprland = Hyprland::new(executor as Arc<dyn hyprland::HyprctlExecutor>);
         let item = StatusNotifierItem::new(window_info, Arc::clone(&notify), hyprland);
@@ -161,12 +226,16 @@ mod tests {
             .unwrap()
             .push_str(r#"{"id": 7}"#);
 
-        item.activate(0, 0);
+        item.activate(0, 0).await;
 
-        let dispatched = mock_executor.dispatched_commands();
-        assert_eq!(dispatched.len(), 2);
-        assert_eq!(dispatched[0], "movetoworkspace 7,address:0xNOTIFY_TEST");
-        assert_eq!(dispatched[1], "focuswindow address:0xNOTIFY_TEST");
+        assert_eq!(
+            mock_executor.dispatched_batches(),
+            [[
+                "movetoworkspace 7,address:0xNOTIFY_TEST",
+                "focuswindow address:0xNOTIFY_TEST"
+            ]]
+        );
+        assert!(mock_executor.dispatched_commands().is_empty());
 
         assert!(
             timeout(Duration::from_millis(10), notify.notified())
@@ -180,7 +249,7 @@ mod tests {
         let mock_executor = Arc::new(MockExecutor::default());
         let (item, notify) = create_test_item(mock_executor.clone());
 
-        item.secondary_activate(0, 0);
+        item.secondary_activate(0, 0).await;
 
         let dispatched = mock_executor.dispatched_commands();
         assert_eq!(dispatched.len(), 1);
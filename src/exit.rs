@@ -0,0 +1,102 @@
+//! The exit codes of the process, so that scripts can tell why a command failed.
+use crate::dbus::control::ControlError;
+use crate::hyprland::HyprlandError;
+use crate::menu::MenuError;
+use crate::minimize::MinimizeError;
+use crate::stack::StackError;
+
+/// Any failure without a more specific code.
+pub const FAILURE: u8 = 1;
+/// Hyprland did not answer, or could not be found.
+pub const UNREACHABLE: u8 = 3;
+/// No window is focused, so there is nothing to minimize.
+pub const NO_ACTIVE_WINDOW: u8 = 4;
+/// No window has the given address.
+pub const WINDOW_NOT_FOUND: u8 = 5;
+/// No StatusNotifierWatcher is running to show the tray icon.
+pub const NO_TRAY_WATCHER: u8 = 6;
+/// There is no minimized window to restore.
+pub const STACK_EMPTY: u8 = 7;
+/// The launcher was dismissed without selecting a window.
+pub const CANCELLED: u8 = 8;
+
+/// Maps an error to the exit code documented for it. Errors returned by a daemon
+/// map to the same codes as when the request is handled locally.
+pub fn code(error: &anyhow::Error) -> u8 {
+    if let Some(error) = error.downcast_ref::<HyprlandError>() {
+        return match error {
+            HyprlandError::Unreachable { .. } => UNREACHABLE,
+            HyprlandError::NoActiveWindow => NO_ACTIVE_WINDOW,
+            HyprlandError::WindowNotFound(_) => WINDOW_NOT_FOUND,
+        };
+    }
+    if let Some(error) = error.downcast_ref::<ControlError>() {
+        return match error {
+            ControlError::Unreachable(_) => UNREACHABLE,
+            ControlError::NoActiveWindow(_) => NO_ACTIVE_WINDOW,
+            ControlError::WindowNotFound(_) => WINDOW_NOT_FOUND,
+            ControlError::StackEmpty(_) => STACK_EMPTY,
+            ControlError::NoTrayWatcher(_) => NO_TRAY_WATCHER,
+            _ => FAILURE,
+        };
+    }
+    if let Some(StackError::Empty) = error.downcast_ref::<StackError>() {
+        return STACK_EMPTY;
+    }
+    if let Some(MinimizeError::NoTrayWatcher) = error.downcast_ref::<MinimizeError>() {
+        return NO_TRAY_WATCHER;
+    }
+    if let Some(MenuError::Cancelled) = error.downcast_ref::<MenuError>() {
+        return CANCELLED;
+    }
+    FAILURE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_typed_errors_map_to_their_codes() {
+        let unreachable = HyprlandError::Unreachable {
+            command: "clients".to_string(),
+            reason: "no answer within 2s".to_string(),
+        };
+        assert_eq!(code(&unreachable.into()), UNREACHABLE);
+        assert_eq!(
+            code(&HyprlandError::NoActiveWindow.into()),
+            NO_ACTIVE_WINDOW
+        );
+        assert_eq!(
+            code(&HyprlandError::WindowNotFound("0x1".to_string()).into()),
+            WINDOW_NOT_FOUND
+        );
+        assert_eq!(code(&MinimizeError::NoTrayWatcher.into()), NO_TRAY_WATCHER);
+        assert_eq!(code(&StackError::Empty.into()), STACK_EMPTY);
+        assert_eq!(code(&MenuError::Cancelled.into()), CANCELLED);
+    }
+
+    #[test]
+    fn test_context_does_not_hide_the_code() {
+        let error = Err::<(), _>(StackError::Empty)
+            .context("The daemon failed to restore the last window.")
+            .unwrap_err();
+        assert_eq!(code(&error), STACK_EMPTY);
+    }
+
+    #[test]
+    fn test_daemon_errors_map_like_local_ones() {
+        let error = anyhow::Error::from(ControlError::WindowNotFound("0x1".to_string()));
+        assert_eq!(code(&error), WINDOW_NOT_FOUND);
+        let error = anyhow::Error::from(ControlError::Failed("oops".to_string()));
+        assert_eq!(code(&error), FAILURE);
+    }
+
+    #[test]
+    fn test_other_errors_are_generic_failures() {
+        assert_eq!(code(&anyhow::anyhow!("Failed to parse JSON")), FAILURE);
+        let invalid = MenuError::InvalidSelection("nonsense".to_string());
+        assert_eq!(code(&invalid.into()), FAILURE);
+    }
+}
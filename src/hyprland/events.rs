@@ -0,0 +1,120 @@
+//! Parsing of the events Hyprland broadcasts on its event socket (`.socket2.sock`).
+
+/// The subset of Hyprland events the minimizer reacts to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// `closewindow>>ADDRESS`
+    CloseWindow { address: String },
+    /// `movewindowv2>>ADDRESS,WORKSPACEID,WORKSPACENAME`
+    MoveWindow {
+        address: String,
+        workspace_id: i32,
+        workspace_name: String,
+    },
+    /// `activewindowv2>>ADDRESS`
+    ActiveWindow { address: String },
+    /// `workspace>>WORKSPACENAME`
+    Workspace { name: String },
+}
+
+impl Event {
+    /// Parses a single `EVENT>>DATA` line. Events the minimizer does not care about,
+    /// and malformed lines, yield `None`.
+    pub fn parse(line: &str) -> Option<Event> {
+        let (name, data) = line.trim_end().split_once(">>")?;
+        match name {
+            "closewindow" => Some(Event::CloseWindow {
+                address: normalize_address(data)?,
+            }),
+            "movewindowv2" => {
+                // The workspace name may itself contain commas, so only split twice.
+                let mut fields = data.splitn(3, ',');
+                let address = normalize_address(fields.next()?)?;
+                let workspace_id = fields.next()?.parse().ok()?;
+                let workspace_name = fields.next()?.to_string();
+                Some(Event::MoveWindow {
+                    address,
+                    workspace_id,
+                    workspace_name,
+                })
+            }
+            "activewindowv2" => Some(Event::ActiveWindow {
+                address: normalize_address(data)?,
+            }),
+            "workspace" => Some(Event::Workspace {
+                name: data.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Events carry bare hex addresses, while `hyprctl clients` reports them with a `0x` prefix.
+fn normalize_address(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    if raw.is_empty() || raw == "," {
+        return None;
+    }
+    if raw.starts_with("0x") {
+        Some(raw.to_string())
+    } else {
+        Some(format!("0x{raw}"))
+    }
+}
+
+// --- Unit Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_close_window() {
+        assert_eq!(
+            Event::parse("closewindow>>55d0c0ffee00\n"),
+            Some(Event::CloseWindow {
+                address: "0x55d0c0ffee00".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_move_window_with_comma_in_name() {
+        assert_eq!(
+            Event::parse("movewindowv2>>55d0c0ffee00,-98,special:min,imized"),
+            Some(Event::MoveWindow {
+                address: "0x55d0c0ffee00".to_string(),
+                workspace_id: -98,
+                workspace_name: "special:min,imized".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_active_window() {
+        assert_eq!(
+            Event::parse("activewindowv2>>55d0c0ffee00"),
+            Some(Event::ActiveWindow {
+                address: "0x55d0c0ffee00".to_string()
+            })
+        );
+        // Hyprland sends an empty address when no window is focused.
+        assert_eq!(Event::parse("activewindowv2>>"), None);
+    }
+
+    #[test]
+    fn test_parse_workspace() {
+        assert_eq!(
+            Event::parse("workspace>>3"),
+            Some(Event::Workspace {
+                name: "3".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_ignores_unknown_and_malformed_lines() {
+        assert_eq!(Event::parse("openwindow>>55d0,1,kitty,kitty"), None);
+        assert_eq!(Event::parse("movewindowv2>>55d0,notanumber,3"), None);
+        assert_eq!(Event::parse("garbage"), None);
+    }
+}
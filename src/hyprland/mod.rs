@@ -0,0 +1,940 @@
+//! Functions and data structures for interacting with Hyprland.
+pub mod events;
+pub mod socket;
+
+pub use socket::SocketExecutor;
+
+use crate::cli::IpcBackend;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::io::{self, ErrorKind};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Output, Stdio};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::process::Command;
+use tokio::time::{Duration, sleep, timeout};
+
+// Hyprland adds fields to its replies and sometimes changes their types between
+// versions. Unknown fields are ignored, and every field but the few that identify
+// an object falls back to its default instead of failing the whole reply.
+
+/// A workspace as reported by `hyprctl workspaces` and `hyprctl activeworkspace`.
+/// Windows and monitors only report its `id` and `name`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[allow(dead_code)]
+pub struct Workspace {
+    pub id: i32,
+    #[serde(default, deserialize_with = "lenient")]
+    pub name: String,
+    /// The name of the monitor showing the workspace.
+    #[serde(default, deserialize_with = "lenient")]
+    pub monitor: String,
+    #[serde(rename = "monitorID", default, deserialize_with = "lenient")]
+    pub monitor_id: Option<i32>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub windows: u32,
+    #[serde(rename = "hasfullscreen", default, deserialize_with = "lenient")]
+    pub has_fullscreen: bool,
+    /// The address of the last focused window.
+    #[serde(rename = "lastwindow", default, deserialize_with = "lenient")]
+    pub last_window: String,
+    #[serde(rename = "lastwindowtitle", default, deserialize_with = "lenient")]
+    pub last_window_title: String,
+    #[serde(rename = "ispersistent", default, deserialize_with = "lenient")]
+    pub is_persistent: bool,
+}
+
+impl Workspace {
+    /// How `movetoworkspace` should refer to the workspace to bring a window back to
+    /// it. Named workspaces get a new id whenever they are recreated, so they are
+    /// referred to by name, which also recreates them when they are gone.
+    pub fn dispatch_target(&self) -> String {
+        if self.name.is_empty() || self.name == self.id.to_string() {
+            self.id.to_string()
+        } else if self.name == "special" || self.name.starts_with("special:") {
+            self.name.clone()
+        } else {
+            format!("name:{}", self.name)
+        }
+    }
+}
+
+/// A monitor as reported by `hyprctl monitors`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
+pub struct Monitor {
+    pub id: i32,
+    pub name: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub description: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub make: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub model: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub width: i32,
+    #[serde(default, deserialize_with = "lenient")]
+    pub height: i32,
+    #[serde(default, deserialize_with = "lenient")]
+    pub refresh_rate: f64,
+    #[serde(default, deserialize_with = "lenient")]
+    pub x: i32,
+    #[serde(default, deserialize_with = "lenient")]
+    pub y: i32,
+    #[serde(default, deserialize_with = "lenient")]
+    pub active_workspace: Workspace,
+    /// The special workspace shown on the monitor, with id 0 when there is none.
+    #[serde(default, deserialize_with = "lenient")]
+    pub special_workspace: Workspace,
+    /// The space reserved by bars and panels: left, top, right and bottom.
+    #[serde(default, deserialize_with = "lenient")]
+    pub reserved: [i32; 4],
+    #[serde(default, deserialize_with = "lenient")]
+    pub scale: f64,
+    #[serde(default, deserialize_with = "lenient")]
+    pub transform: i32,
+    #[serde(default, deserialize_with = "lenient")]
+    pub focused: bool,
+    #[serde(default, deserialize_with = "lenient")]
+    pub disabled: bool,
+}
+
+impl Monitor {
+    /// Whether a point in global layout coordinates lies on the monitor. Its area in
+    /// the layout is its mode, rotated by its transform and divided by its scale.
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        let (width, height) = if self.transform % 2 == 1 {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        };
+        let scale = if self.scale > 0.0 { self.scale } else { 1.0 };
+        let (left, top) = (f64::from(self.x), f64::from(self.y));
+        (left..left + f64::from(width) / scale).contains(&x)
+            && (top..top + f64::from(height) / scale).contains(&y)
+    }
+}
+
+/// The position of the cursor as reported by `hyprctl cursorpos`, in global layout
+/// coordinates.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct CursorPosition {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// A window as reported by `hyprctl clients` and `hyprctl activewindow`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(remote = "Self", rename_all = "camelCase")]
+#[allow(dead_code)]
+pub struct WindowInfo {
+    pub address: String,
+    pub workspace: Workspace,
+    pub title: String,
+    pub class: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub initial_class: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub initial_title: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub pid: i32,
+    /// The position of the top-left corner, in global layout coordinates.
+    #[serde(default, deserialize_with = "lenient")]
+    pub at: [i32; 2],
+    #[serde(default, deserialize_with = "lenient")]
+    pub size: [i32; 2],
+    /// The id of the monitor showing the window.
+    #[serde(default, deserialize_with = "lenient")]
+    pub monitor: i32,
+    #[serde(default, deserialize_with = "lenient")]
+    pub floating: bool,
+    /// The fullscreen mode: 0 for none, 1 for maximized, 2 for fullscreen.
+    #[serde(default, deserialize_with = "lenient")]
+    pub fullscreen: u8,
+    #[serde(default, deserialize_with = "lenient")]
+    pub pinned: bool,
+    /// The addresses of the windows in the window's group, itself included, or
+    /// empty when it is not grouped.
+    #[serde(default, deserialize_with = "lenient")]
+    pub grouped: Vec<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub xwayland: bool,
+    /// How recently the window was focused: 0 for the focused one, then 1, 2...
+    #[serde(rename = "focusHistoryID", default, deserialize_with = "lenient")]
+    pub focus_history_id: i32,
+}
+
+impl WindowInfo {
+    /// The window without its floating geometry and fullscreen mode, so that
+    /// restoring it leaves them to Hyprland. The pin is kept, as minimizing takes it
+    /// away to hide the window.
+    pub fn without_state(&self) -> WindowInfo {
+        WindowInfo {
+            at: [0, 0],
+            size: [0, 0],
+            floating: false,
+            fullscreen: 0,
+            ..self.clone()
+        }
+    }
+}
+
+/// Deserializes a field that falls back to its default when its value has an
+/// unexpected type, e.g. after Hyprland changed it.
+fn lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(T::deserialize(value).unwrap_or_default())
+}
+
+impl Serialize for WindowInfo {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        WindowInfo::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for WindowInfo {
+    /// Older Hyprland versions report `fullscreen` as a boolean and the mode apart,
+    /// as `fullscreenMode`: 0 for fullscreen, 1 for maximized. Newer ones report
+    /// the mode itself as `fullscreen`.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut value = serde_json::Value::deserialize(deserializer)?;
+        if let Some(fullscreen) = value.get("fullscreen").and_then(|v| v.as_bool()) {
+            let legacy_mode = value.get("fullscreenMode").and_then(|v| v.as_u64());
+            value["fullscreen"] = match (fullscreen, legacy_mode) {
+                (false, _) => 0,
+                (true, Some(1)) => 1,
+                (true, _) => 2,
+            }
+            .into();
+        }
+        WindowInfo::deserialize(value).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A trait that abstracts the execution of `hyprctl` commands.
+/// It must be Send + Sync to be used across threads, and never blocks the runtime.
+#[async_trait]
+pub trait HyprctlExecutor: Send + Sync {
+    async fn execute_json(&self, command: &str) -> Result<Output>;
+    async fn execute_dispatch(&self, command: &str) -> Result<Output>;
+    /// Runs several dispatches in a single request, in order.
+    async fn execute_batch(&self, commands: &[String]) -> Result<Output>;
+}
+
+/// Builds the argument of `hyprctl --batch` for a list of dispatches.
+fn batch_request(commands: &[String]) -> String {
+    commands
+        .iter()
+        .map(|command| format!("dispatch {command}"))
+        .collect::<Vec<_>>()
+        .join(" ; ")
+}
+
+/// Hyprland answers a batch with one reply per command, separated by blank lines.
+/// The batch only succeeded if every reply is "ok"; otherwise the failing replies
+/// become the error output.
+fn batch_output(reply: Vec<u8>) -> Output {
+    let text = String::from_utf8_lossy(&reply);
+    let failures: Vec<&str> = text
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|reply| !reply.is_empty() && *reply != "ok")
+        .collect();
+    if failures.is_empty() {
+        Output {
+            status: ExitStatus::from_raw(0),
+            stdout: reply,
+            stderr: vec![],
+        }
+    } else {
+        Output {
+            status: ExitStatus::from_raw(1 << 8),
+            stdout: vec![],
+            stderr: failures.join("; ").into_bytes(),
+        }
+    }
+}
+
+/// The executor that runs the actual `hyprctl` command asynchronously, against a specific instance
+/// when one is given.
+#[derive(Default)]
+pub struct LiveExecutor {
+    instance: Option<String>,
+}
+
+impl LiveExecutor {
+    fn hyprctl(&self) -> Command {
+        let mut command = Command::new("hyprctl");
+        // A request given up on after a timeout must not leave hyprctl running.
+        command.kill_on_drop(true);
+        if let Some(instance) = &self.instance {
+            command.arg("-i").arg(instance);
+        }
+        command
+    }
+}
+
+#[async_trait]
+impl HyprctlExecutor for LiveExecutor {
+    async fn execute_json(&self, command: &str) -> Result<Output> {
+        self.hyprctl()
+            .arg("-j")
+            .arg(command)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .with_context(|| format!("Failed to execute hyprctl json command: {command}"))
+    }
+
+    async fn execute_dispatch(&self, command: &str) -> Result<Output> {
+        self.hyprctl()
+            .arg("dispatch")
+            .arg(command)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .with_context(|| format!("Failed to execute hyprctl dispatch: {command}"))
+    }
+
+    async fn execute_batch(&self, commands: &[String]) -> Result<Output> {
+        let request = batch_request(commands);
+        let output = self
+            .hyprctl()
+            .arg("--batch")
+            .arg(&request)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .with_context(|| format!("Failed to execute hyprctl batch: {request}"))?;
+        if !output.status.success() {
+            return Ok(output);
+        }
+        Ok(batch_output(output.stdout))
+    }
+}
+
+/// Picks the executor for the configured backend and instance. The socket backend
+/// falls back to `hyprctl` when the Hyprland socket cannot be found, which also lets
+/// `hyprctl` resolve instances given by index.
+pub fn executor_for(backend: IpcBackend, instance: Option<&str>) -> Arc<dyn HyprctlExecutor> {
+    let live = LiveExecutor {
+        instance: instance.map(str::to_string),
+    };
+    match backend {
+        IpcBackend::Socket => match SocketExecutor::for_instance(instance) {
+            Ok(executor) => Arc::new(executor),
+            Err(_) => Arc::new(live),
+        },
+        IpcBackend::Hyprctl => Arc::new(live),
+    }
+}
+
+/// The failures of Hyprland requests that callers may want to tell apart from a
+/// request Hyprland simply answered with an error.
+#[derive(Debug, Error)]
+pub enum HyprlandError {
+    /// Hyprland could not be reached at all. See [`is_unreachable`].
+    #[error("Hyprland is unreachable for '{command}': {reason}")]
+    Unreachable { command: String, reason: String },
+    #[error("No window is focused.")]
+    NoActiveWindow,
+    #[error("Could not find a window with address '{0}'")]
+    WindowNotFound(String),
+}
+
+/// Whether `error` means that Hyprland could not be reached.
+pub fn is_unreachable(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<HyprlandError>(),
+        Some(HyprlandError::Unreachable { .. })
+    )
+}
+
+/// How long to wait for each request to Hyprland, and how to retry transient failures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CallPolicy {
+    pub timeout: Duration,
+    pub retries: u32,
+    /// The delay before the first retry, doubled before each following one.
+    pub backoff: Duration,
+}
+
+impl Default for CallPolicy {
+    fn default() -> Self {
+        CallPolicy {
+            timeout: Duration::from_secs(2),
+            retries: 2,
+            backoff: Duration::from_millis(50),
+        }
+    }
+}
+
+/// Whether a failure to reach Hyprland is worth retrying, e.g. a busy socket.
+fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause.downcast_ref::<io::Error>().is_some_and(|e| {
+            matches!(
+                e.kind(),
+                ErrorKind::WouldBlock | ErrorKind::Interrupted | ErrorKind::ResourceBusy
+            )
+        })
+    })
+}
+
+#[derive(Clone)]
+pub struct Hyprland {
+    executor: Arc<dyn HyprctlExecutor>,
+    instance: Option<String>,
+    policy: CallPolicy,
+}
+
+impl Hyprland {
+    pub fn new(executor: Arc<dyn HyprctlExecutor>) -> Self {
+        Hyprland {
+            executor,
+            instance: None,
+            policy: CallPolicy::default(),
+        }
+    }
+
+    /// Overrides the default timeout and retries of every request.
+    pub fn with_call_policy(mut self, policy: CallPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Targets a specific Hyprland instance rather than the one this process runs under.
+    pub fn with_instance(mut self, instance: Option<String>) -> Self {
+        self.instance = instance;
+        self
+    }
+
+    /// The signature of the targeted instance, if one was given.
+    pub fn instance(&self) -> Option<&str> {
+        self.instance.as_deref()
+    }
+
+    /// Executes a hyprctl command and returns the parsed JSON output.
+    pub async fn exec<T: for<'de> Deserialize<'de>>(&self, command: &str) -> Result<T> {
+        let output = self
+            .call(command, || self.executor.execute_json(command))
+            .await?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("hyprctl command '{command}' failed: {stderr}");
+        }
+
+        serde_json::from_slice(&output.stdout)
+            .with_context(|| format!("Failed to parse JSON from hyprctl command: {command}"))
+    }
+
+    /// Executes a hyprctl dispatch command.
+    pub async fn dispatch(&self, command: &str) -> Result<()> {
+        let output = self
+            .call(command, || self.executor.execute_dispatch(command))
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("hyprctl dispatch command '{command}' failed: {stderr}");
+        }
+        Ok(())
+    }
+
+    /// Executes several dispatch commands in a single request, so that they are
+    /// applied together rather than one round trip at a time.
+    pub async fn dispatch_batch(&self, commands: &[String]) -> Result<()> {
+        let request = batch_request(commands);
+        let output = self
+            .call(&request, || self.executor.execute_batch(commands))
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("hyprctl batch '{request}' failed: {stderr}");
+        }
+        Ok(())
+    }
+
+    /// Runs a request under the call policy: each attempt is bounded by the timeout,
+    /// and transient failures are retried with exponential backoff. Failing to get
+    /// any answer is reported as [`HyprlandError::Unreachable`].
+    async fn call<F, Fut>(&self, command: &str, request: F) -> Result<Output>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<Output>>,
+    {
+        let mut backoff = self.policy.backoff;
+        let mut attempt = 0;
+        loop {
+            let error = match timeout(self.policy.timeout, request()).await {
+                Ok(Ok(output)) => return Ok(output),
+                Ok(Err(e)) => e,
+                Err(_) => {
+                    return Err(HyprlandError::Unreachable {
+                        command: command.to_string(),
+                        reason: format!("no answer within {:?}", self.policy.timeout),
+                    }
+                    .into());
+                }
+            };
+            if attempt < self.policy.retries && is_transient(&error) {
+                attempt += 1;
+                sleep(backoff).await;
+                backoff *= 2;
+                continue;
+            }
+            return Err(HyprlandError::Unreachable {
+                command: command.to_string(),
+                reason: format!("{error:#}"),
+            }
+            .into());
+        }
+    }
+
+    /// Finds a window by its address from the list of all clients.
+    pub async fn get_window_by_address(&self, address: &str) -> Result<WindowInfo> {
+        let clients: Vec<WindowInfo> = self
+            .exec("clients")
+            .await
+            .context("Failed to get client list from Hyprland.")?;
+        clients
+            .into_iter()
+            .find(|c| c.address == address)
+            .ok_or_else(|| HyprlandError::WindowNotFound(address.to_string()).into())
+    }
+
+    /// Gets the focused window. Hyprland answers with an empty object when there is none.
+    pub async fn active_window(&self) -> Result<WindowInfo> {
+        let reply: serde_json::Value = self.exec("activewindow").await?;
+        if reply.as_object().is_some_and(|window| window.is_empty()) {
+            return Err(HyprlandError::NoActiveWindow.into());
+        }
+        serde_json::from_value(reply).context("Failed to parse the active window from Hyprland.")
+    }
+}
+
+// --- Unit Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Mock executor for testing.
+    #[derive(Default)]
+    struct MockExecutor {
+        stdout: String,
+        is_success: bool,
+    }
+    #[async_trait]
+    impl HyprctlExecutor for MockExecutor {
+        async fn execute_json(&self, _command: &str) -> Result<Output> {
+            Ok(Output {
+                status: ExitStatus::from_raw(if self.is_success { 0 } else { 1 }),
+                stdout: self.stdout.as_bytes().to_vec(),
+                stderr: b"Mock failure".to_vec(),
+            })
+        }
+        async fn execute_dispatch(&self, _command: &str) -> Result<Output> {
+            Ok(Output {
+                status: ExitStatus::from_raw(if self.is_success { 0 } else { 1 }),
+                stdout: vec![],
+                stderr: vec![],
+            })
+        }
+        async fn execute_batch(&self, _commands: &[String]) -> Result<Output> {
+            self.execute_dispatch("").await
+        }
+    }
+
+    /// Fails with `kind` a number of times before answering, counting its calls.
+    struct FlakyExecutor {
+        kind: ErrorKind,
+        failures: u32,
+        calls: Mutex<u32>,
+    }
+    impl FlakyExecutor {
+        fn new(kind: ErrorKind, failures: u32) -> Self {
+            FlakyExecutor {
+                kind,
+                failures,
+                calls: Mutex::new(0),
+            }
+        }
+        fn calls(&self) -> u32 {
+            *self.calls.lock().unwrap()
+        }
+    }
+    #[async_trait]
+    impl HyprctlExecutor for FlakyExecutor {
+        async fn execute_json(&self, _command: &str) -> Result<Output> {
+            let mut calls = self.calls.lock().unwrap();
+            *calls += 1;
+            if *calls <= self.failures {
+                return Err(io::Error::from(self.kind)).context("Failed to connect");
+            }
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: b"{\"id\": 1}".to_vec(),
+                stderr: vec![],
+            })
+        }
+        async fn execute_dispatch(&self, command: &str) -> Result<Output> {
+            self.execute_json(command).await
+        }
+        async fn execute_batch(&self, _commands: &[String]) -> Result<Output> {
+            self.execute_json("").await
+        }
+    }
+
+    /// Never answers, like a hung compositor.
+    struct HungExecutor;
+    #[async_trait]
+    impl HyprctlExecutor for HungExecutor {
+        async fn execute_json(&self, _command: &str) -> Result<Output> {
+            std::future::pending().await
+        }
+        async fn execute_dispatch(&self, _command: &str) -> Result<Output> {
+            std::future::pending().await
+        }
+        async fn execute_batch(&self, _commands: &[String]) -> Result<Output> {
+            std::future::pending().await
+        }
+    }
+
+    fn fast_policy() -> CallPolicy {
+        CallPolicy {
+            timeout: Duration::from_millis(50),
+            retries: 2,
+            backoff: Duration::from_millis(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_window_by_address_success() {
+        let mock_json =
+            r#"[{"address": "0x456", "workspace": {"id": 2}, "title": "Kitty", "class": "kitty"}]"#;
+        let mock_executor = Arc::new(MockExecutor {
+            stdout: mock_json.to_string(),
+            is_success: true,
+        });
+        let hyprland = Hyprland::new(mock_executor);
+
+        let result = hyprland.get_window_by_address("0x456").await;
+
+        assert!(result.is_ok());
+        let window = result.unwrap();
+        assert_eq!(window.title, "Kitty");
+    }
+
+    #[test]
+    fn test_window_info_accepts_both_fullscreen_formats() {
+        let legacy: WindowInfo = serde_json::from_str(
+            r#"{"address": "0x1", "workspace": {"id": 1, "name": "1"}, "title": "", "class": "", "fullscreen": true}"#,
+        )
+        .unwrap();
+        let legacy_maximized: WindowInfo = serde_json::from_str(
+            r#"{"address": "0x1", "workspace": {"id": 1, "name": "1"}, "title": "", "class": "", "fullscreen": true, "fullscreenMode": 1}"#,
+        )
+        .unwrap();
+        let current: WindowInfo = serde_json::from_str(
+            r#"{"address": "0x1", "workspace": {"id": 1, "name": "1"}, "title": "", "class": "", "fullscreen": 1}"#,
+        )
+        .unwrap();
+
+        assert_eq!(legacy.fullscreen, 2);
+        assert_eq!(legacy_maximized.fullscreen, 1);
+        assert_eq!(current.fullscreen, 1);
+    }
+
+    /// Replies of `hyprctl -j clients`, `monitors` and `workspaces` captured from
+    /// several Hyprland versions.
+    const FIXTURES: [(&str, &str, &str, &str); 3] = [
+        (
+            "v0.28.0",
+            include_str!("fixtures/v0.28.0/clients.json"),
+            include_str!("fixtures/v0.28.0/monitors.json"),
+            include_str!("fixtures/v0.28.0/workspaces.json"),
+        ),
+        (
+            "v0.34.0",
+            include_str!("fixtures/v0.34.0/clients.json"),
+            include_str!("fixtures/v0.34.0/monitors.json"),
+            include_str!("fixtures/v0.34.0/workspaces.json"),
+        ),
+        (
+            "v0.45.2",
+            include_str!("fixtures/v0.45.2/clients.json"),
+            include_str!("fixtures/v0.45.2/monitors.json"),
+            include_str!("fixtures/v0.45.2/workspaces.json"),
+        ),
+    ];
+
+    fn fixture(version: &str) -> (Vec<WindowInfo>, Vec<Monitor>, Vec<Workspace>) {
+        let (_, clients, monitors, workspaces) =
+            FIXTURES.iter().find(|(v, ..)| *v == version).unwrap();
+        (
+            serde_json::from_str(clients).unwrap(),
+            serde_json::from_str(monitors).unwrap(),
+            serde_json::from_str(workspaces).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_workspace_dispatch_target() {
+        let workspace = |id: i32, name: &str| Workspace {
+            id,
+            name: name.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(workspace(3, "3").dispatch_target(), "3");
+        assert_eq!(workspace(3, "").dispatch_target(), "3");
+        assert_eq!(workspace(-1337, "code").dispatch_target(), "name:code");
+        assert_eq!(workspace(7, "web").dispatch_target(), "name:web");
+        assert_eq!(
+            workspace(-97, "special:term").dispatch_target(),
+            "special:term"
+        );
+    }
+
+    #[test]
+    fn test_monitor_contains_scaled_and_rotated_areas() {
+        let (_, monitors, _) = fixture("v0.34.0");
+        // eDP-1 is 2256x1504 at scale 1.5, so 1504x1003 in the layout, and DP-2 at
+        // the same scale starts right after it.
+        let (laptop, external) = (&monitors[0], &monitors[1]);
+        assert!(laptop.contains(0.0, 0.0));
+        assert!(laptop.contains(1503.0, 1002.0));
+        assert!(!laptop.contains(1504.0, 10.0));
+        assert!(external.contains(1504.0, 10.0));
+        assert!(!external.contains(1504.0, 1440.0));
+
+        let portrait = Monitor {
+            width: 1920,
+            height: 1080,
+            scale: 1.0,
+            transform: 1,
+            ..Default::default()
+        };
+        assert!(portrait.contains(1000.0, 1900.0));
+        assert!(!portrait.contains(1900.0, 1000.0));
+    }
+
+    #[test]
+    fn test_every_fixture_parses() {
+        for (version, ..) in FIXTURES {
+            let (clients, monitors, workspaces) = fixture(version);
+            assert_eq!(clients.len(), 2, "{version}");
+            assert!(clients.iter().all(|c| !c.address.is_empty() && c.pid > 0));
+            assert!(monitors.iter().all(|m| m.width > 0 && m.scale > 0.0));
+            assert!(workspaces.iter().all(|w| !w.name.is_empty()));
+        }
+    }
+
+    #[test]
+    fn test_fixture_v0_28() {
+        let (clients, monitors, workspaces) = fixture("v0.28.0");
+
+        let calculator = &clients[1];
+        assert_eq!(calculator.workspace.id, -99);
+        assert_eq!(calculator.workspace.name, "special:minimized");
+        assert_eq!(calculator.initial_class, "org.gnome.Calculator");
+        assert_eq!((calculator.at, calculator.size), ([660, 290], [600, 500]));
+        assert!(calculator.floating);
+        // Not reported yet by this version.
+        assert_eq!(calculator.focus_history_id, 0);
+
+        assert!(!monitors[0].disabled);
+        assert_eq!(monitors[0].reserved, [0, 30, 0, 0]);
+        assert_eq!(workspaces[1].monitor_id, None);
+        assert_eq!(workspaces[1].last_window, "0x55d2b9b41a70");
+    }
+
+    #[test]
+    fn test_fixture_v0_34() {
+        let (clients, monitors, workspaces) = fixture("v0.34.0");
+
+        let (firefox, spotify) = (&clients[0], &clients[1]);
+        assert_eq!(firefox.fullscreen, 2);
+        assert_eq!(
+            firefox.grouped,
+            [firefox.address.clone(), spotify.address.clone()]
+        );
+        assert_eq!(firefox.initial_title, "Mozilla Firefox");
+        assert!(spotify.xwayland);
+        assert_eq!(spotify.focus_history_id, 1);
+        assert_eq!(spotify.monitor, 1);
+
+        let focused = monitors.iter().find(|m| m.focused).unwrap();
+        assert_eq!(focused.name, "DP-2");
+        assert_eq!((focused.x, focused.refresh_rate), (1504, 60.0));
+        assert_eq!(focused.active_workspace.id, 2);
+        assert_eq!(focused.special_workspace.name, "special:minimized");
+        assert_eq!(workspaces[1].monitor_id, Some(1));
+        assert!(workspaces[1].has_fullscreen);
+    }
+
+    #[test]
+    fn test_fixture_v0_45() {
+        let (clients, monitors, workspaces) = fixture("v0.45.2");
+
+        let (code, mpv) = (&clients[0], &clients[1]);
+        assert_eq!(code.workspace.name, "code");
+        assert_eq!(code.fullscreen, 1);
+        assert!(mpv.pinned && mpv.floating);
+        assert!(mpv.grouped.is_empty());
+
+        assert_eq!(monitors[1].name, "HDMI-A-1");
+        assert_eq!(monitors[1].special_workspace.id, 0);
+        assert_eq!(workspaces[0].monitor, "DP-1");
+        assert!(workspaces[0].is_persistent);
+        assert_eq!(workspaces[0].windows, 2);
+    }
+
+    #[test]
+    fn test_changed_field_types_fall_back_to_defaults() {
+        let window: WindowInfo = serde_json::from_str(
+            r#"{"address": "0x1", "workspace": {"id": 1, "name": "1", "windows": -1},
+                "title": "t", "class": "c", "pid": 42, "pinned": "yes", "at": "10,20",
+                "grouped": null, "fullscreen": "full", "focusHistoryID": 3,
+                "someFutureField": {"nested": [1, 2]}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(window.pid, 42);
+        assert!(!window.pinned);
+        assert_eq!(window.at, [0, 0]);
+        assert!(window.grouped.is_empty());
+        assert_eq!(window.fullscreen, 0);
+        assert_eq!(window.focus_history_id, 3);
+        assert_eq!(window.workspace.windows, 0);
+
+        // The fields that identify a window are still required.
+        assert!(serde_json::from_str::<WindowInfo>(r#"{"title": "t", "class": "c"}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_hyprctl_command_failure() {
+        let mock_executor = Arc::new(MockExecutor {
+            stdout: "".to_string(),
+            is_success: false, // Simulate a command failure.
+        });
+        let hyprland = Hyprland::new(mock_executor);
+
+        let result = hyprland.get_window_by_address("any").await;
+        assert!(result.is_err());
+
+        let err_string = format!("{:?}", result.unwrap_err());
+
+        assert!(err_string.contains("Failed to get client list from Hyprland."));
+        assert!(err_string.contains("hyprctl command 'clients' failed: Mock failure"));
+    }
+
+    #[test]
+    fn test_batch_request_and_replies() {
+        let commands = [
+            "movetoworkspace 3,address:0x1".to_string(),
+            "focuswindow address:0x1".to_string(),
+        ];
+        assert_eq!(
+            batch_request(&commands),
+            "dispatch movetoworkspace 3,address:0x1 ; dispatch focuswindow address:0x1"
+        );
+
+        assert!(batch_output(b"ok\n\nok".to_vec()).status.success());
+        let failed = batch_output(b"ok\n\nNo such window found".to_vec());
+        assert!(!failed.status.success());
+        assert_eq!(failed.stderr, b"No such window found");
+    }
+
+    #[tokio::test]
+    async fn test_transient_failures_are_retried() {
+        let executor = Arc::new(FlakyExecutor::new(ErrorKind::WouldBlock, 2));
+        let hyprland = Hyprland::new(executor.clone()).with_call_policy(fast_policy());
+
+        let workspace: Workspace = hyprland.exec("activeworkspace").await.unwrap();
+
+        assert_eq!(workspace.id, 1);
+        assert_eq!(executor.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retries_are_bounded() {
+        let executor = Arc::new(FlakyExecutor::new(ErrorKind::WouldBlock, 10));
+        let hyprland = Hyprland::new(executor.clone()).with_call_policy(fast_policy());
+
+        let error = hyprland
+            .dispatch("focuswindow address:0x1")
+            .await
+            .unwrap_err();
+
+        assert!(is_unreachable(&error));
+        assert_eq!(executor.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_permanent_failures_are_not_retried() {
+        let executor = Arc::new(FlakyExecutor::new(ErrorKind::NotFound, 1));
+        let hyprland = Hyprland::new(executor.clone()).with_call_policy(fast_policy());
+
+        let error = hyprland
+            .exec::<Workspace>("activeworkspace")
+            .await
+            .unwrap_err();
+
+        assert!(is_unreachable(&error));
+        assert_eq!(executor.calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_hung_compositor_times_out_as_unreachable() {
+        let hyprland = Hyprland::new(Arc::new(HungExecutor)).with_call_policy(fast_policy());
+
+        let error = hyprland.get_window_by_address("0x1").await.unwrap_err();
+
+        // The context added on top does not hide the cause.
+        assert!(is_unreachable(&error));
+        assert!(format!("{error:#}").contains("no answer within"));
+    }
+
+    #[tokio::test]
+    async fn test_window_not_found_is_not_unreachable() {
+        let hyprland = Hyprland::new(Arc::new(MockExecutor {
+            stdout: "[]".to_string(),
+            is_success: true,
+        }));
+
+        let error = hyprland.get_window_by_address("0x1").await.unwrap_err();
+
+        assert!(!is_unreachable(&error));
+        assert!(matches!(
+            error.downcast_ref::<HyprlandError>(),
+            Some(HyprlandError::WindowNotFound(address)) if address == "0x1"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_no_active_window() {
+        let hyprland = Hyprland::new(Arc::new(MockExecutor {
+            stdout: "{}".to_string(),
+            is_success: true,
+        }));
+
+        let error = hyprland.active_window().await.unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<HyprlandError>(),
+            Some(HyprlandError::NoActiveWindow)
+        ));
+    }
+}
@@ -0,0 +1,302 @@
+//! An executor that speaks Hyprland's IPC protocol over its request socket.
+use super::{HyprctlExecutor, batch_output, batch_request};
+
+use anyhow::{Context, Result, bail};
+use async_trait::async_trait;
+use std::env;
+use std::fs;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Output};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+/// The signature of the Hyprland instance to talk to: the given one, or the one
+/// this process was started under. Like `hyprctl -i`, a number picks an instance by
+/// its index in `hyprctl instances`.
+pub fn instance_signature(instance: Option<&str>) -> Result<String> {
+    if let Some(instance) = instance {
+        if !instance.is_empty() && instance.bytes().all(|b| b.is_ascii_digit()) {
+            let Ok(runtime_dir) = env::var("XDG_RUNTIME_DIR") else {
+                bail!("Could not find the XDG_RUNTIME_DIR environment variable.");
+            };
+            return instance_by_index(&PathBuf::from(runtime_dir).join("hypr"), instance);
+        }
+        return Ok(instance.to_string());
+    }
+    match env::var("HYPRLAND_INSTANCE_SIGNATURE") {
+        Ok(signature) => Ok(signature),
+        Err(_) => bail!("Could not find the HYPRLAND_INSTANCE_SIGNATURE environment variable."),
+    }
+}
+
+/// The signature of the running instance numbered `index`, counting from the oldest
+/// one as `hyprctl instances` does.
+fn instance_by_index(hypr_dir: &Path, index: &str) -> Result<String> {
+    let instances = running_instances(hypr_dir);
+    index
+        .parse::<usize>()
+        .ok()
+        .and_then(|index| instances.get(index).cloned())
+        .with_context(|| {
+            format!(
+                "There is no Hyprland instance number {index}, only {} running.",
+                instances.len()
+            )
+        })
+}
+
+/// The signatures of the Hyprland instances in `hypr_dir` whose process still runs,
+/// sorted by the start time their signature embeds (`<hash>_<time>_<random>`).
+fn running_instances(hypr_dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(hypr_dir) else {
+        return Vec::new();
+    };
+    let mut instances: Vec<(u64, String)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let signature = entry.file_name().into_string().ok()?;
+            // The lock file starts with the pid of the instance.
+            let lock = fs::read_to_string(entry.path().join("hyprland.lock")).ok()?;
+            let pid: u32 = lock.lines().next()?.trim().parse().ok()?;
+            if !Path::new(&format!("/proc/{pid}")).exists() {
+                return None;
+            }
+            let time = signature
+                .split('_')
+                .nth(1)
+                .and_then(|time| time.parse().ok())
+                .unwrap_or(0);
+            Some((time, signature))
+        })
+        .collect();
+    instances.sort();
+    instances
+        .into_iter()
+        .map(|(_, signature)| signature)
+        .collect()
+}
+
+/// Builds the path of the request socket for a Hyprland instance:
+/// `$XDG_RUNTIME_DIR/hypr/<signature>/.socket.sock`.
+pub fn request_socket_path(instance: Option<&str>) -> Result<PathBuf> {
+    instance_socket_path(instance, ".socket.sock")
+}
+
+/// Builds the path of the event socket for a Hyprland instance:
+/// `$XDG_RUNTIME_DIR/hypr/<signature>/.socket2.sock`.
+pub fn event_socket_path(instance: Option<&str>) -> Result<PathBuf> {
+    instance_socket_path(instance, ".socket2.sock")
+}
+
+fn instance_socket_path(instance: Option<&str>, name: &str) -> Result<PathBuf> {
+    let Ok(runtime_dir) = env::var("XDG_RUNTIME_DIR") else {
+        bail!("Could not find the XDG_RUNTIME_DIR environment variable.");
+    };
+    let signature = instance_signature(instance)?;
+    Ok(PathBuf::from(runtime_dir)
+        .join("hypr")
+        .join(signature)
+        .join(name))
+}
+
+/// The executor that writes requests directly to Hyprland's `.socket.sock`,
+/// avoiding a `hyprctl` process per call.
+pub struct SocketExecutor {
+    path: PathBuf,
+}
+
+impl SocketExecutor {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        SocketExecutor { path: path.into() }
+    }
+
+    /// Creates an executor for the given instance, or the running one, failing if
+    /// its socket does not exist.
+    pub fn for_instance(instance: Option<&str>) -> Result<Self> {
+        let path = request_socket_path(instance)?;
+        if !path.exists() {
+            bail!("Hyprland socket not found at {:?}", path);
+        }
+        Ok(SocketExecutor::new(path))
+    }
+
+    /// Sends a single request and reads the reply until Hyprland closes the connection.
+    async fn request(&self, request: &str) -> Result<Vec<u8>> {
+        let mut stream = UnixStream::connect(&self.path)
+            .await
+            .with_context(|| format!("Failed to connect to Hyprland socket at {:?}", self.path))?;
+        stream
+            .write_all(request.as_bytes())
+            .await
+            .with_context(|| format!("Failed to send request to Hyprland: {request}"))?;
+        stream
+            .shutdown()
+            .await
+            .context("Failed to finish request to Hyprland")?;
+
+        let mut response = Vec::new();
+        stream
+            .read_to_end(&mut response)
+            .await
+            .with_context(|| format!("Failed to read Hyprland reply to: {request}"))?;
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl HyprctlExecutor for SocketExecutor {
+    async fn execute_json(&self, command: &str) -> Result<Output> {
+        let stdout = self.request(&format!("j/{command}")).await?;
+        Ok(Output {
+            status: ExitStatus::from_raw(0),
+            stdout,
+            stderr: vec![],
+        })
+    }
+
+    async fn execute_dispatch(&self, command: &str) -> Result<Output> {
+        let response = self.request(&format!("dispatch {command}")).await?;
+        // Hyprland answers "ok" to a successful dispatch and an error message otherwise.
+        if String::from_utf8_lossy(&response).trim() == "ok" {
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: response,
+                stderr: vec![],
+            })
+        } else {
+            Ok(Output {
+                status: ExitStatus::from_raw(1 << 8),
+                stdout: vec![],
+                stderr: response,
+            })
+        }
+    }
+
+    async fn execute_batch(&self, commands: &[String]) -> Result<Output> {
+        let response = self
+            .request(&format!("[[BATCH]]{}", batch_request(commands)))
+            .await?;
+        Ok(batch_output(response))
+    }
+}
+
+// --- Unit Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hyprland::Hyprland;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+    use tempfile::{TempDir, tempdir};
+
+    /// Starts a stand-in for Hyprland's socket that answers a single request
+    /// with `reply` and hands back the request it received.
+    fn serve_once(reply: &'static str) -> (TempDir, PathBuf, JoinHandle<String>) {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(".socket.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            stream.read_to_string(&mut request).unwrap();
+            stream.write_all(reply.as_bytes()).unwrap();
+            request
+        });
+        (dir, path, handle)
+    }
+
+    #[test]
+    fn test_numbered_instances_are_ordered_by_start_time() {
+        let dir = tempdir().unwrap();
+        let add_instance = |signature: &str, pid: u32| {
+            let path = dir.path().join(signature);
+            fs::create_dir(&path).unwrap();
+            fs::write(path.join("hyprland.lock"), format!("{pid}\nwayland-1\n")).unwrap();
+        };
+        let pid = std::process::id();
+        add_instance("abc_1700000200_3", pid);
+        add_instance("def_1700000100_2", pid);
+        // Instances whose process is gone are skipped, as are directories without a lock.
+        add_instance("ghi_1700000000_1", u32::MAX);
+        fs::create_dir(dir.path().join("jkl_1600000000_0")).unwrap();
+
+        assert_eq!(
+            instance_by_index(dir.path(), "0").unwrap(),
+            "def_1700000100_2"
+        );
+        assert_eq!(
+            instance_by_index(dir.path(), "1").unwrap(),
+            "abc_1700000200_3"
+        );
+        assert!(instance_by_index(dir.path(), "2").is_err());
+        assert_eq!(
+            instance_signature(Some("abc_1700000200_3")).unwrap(),
+            "abc_1700000200_3"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_execute_json_sends_json_request() {
+        let (_dir, path, server) = serve_once(
+            r#"[{"address": "0x456", "workspace": {"id": 2}, "title": "Kitty", "class": "kitty"}]"#,
+        );
+        let hyprland = Hyprland::new(Arc::new(SocketExecutor::new(&path)));
+
+        let window = hyprland.get_window_by_address("0x456").await.unwrap();
+
+        assert_eq!(window.class, "kitty");
+        assert_eq!(server.join().unwrap(), "j/clients");
+    }
+
+    #[tokio::test]
+    async fn test_execute_dispatch_success() {
+        let (_dir, path, server) = serve_once("ok");
+        let hyprland = Hyprland::new(Arc::new(SocketExecutor::new(&path)));
+
+        hyprland.dispatch("focuswindow address:0x1").await.unwrap();
+
+        assert_eq!(server.join().unwrap(), "dispatch focuswindow address:0x1");
+    }
+
+    #[tokio::test]
+    async fn test_execute_dispatch_failure_reports_reply() {
+        let (_dir, path, server) = serve_once("Invalid dispatcher");
+        let hyprland = Hyprland::new(Arc::new(SocketExecutor::new(&path)));
+
+        let result = hyprland.dispatch("nonsense").await;
+        server.join().unwrap();
+
+        let err_string = result.unwrap_err().to_string();
+        assert!(err_string.contains("Invalid dispatcher"));
+    }
+
+    #[tokio::test]
+    async fn test_execute_batch_sends_one_request() {
+        let (_dir, path, server) = serve_once("ok\n\nok");
+        let hyprland = Hyprland::new(Arc::new(SocketExecutor::new(&path)));
+
+        hyprland
+            .dispatch_batch(&[
+                "movetoworkspace 3,address:0x1".to_string(),
+                "focuswindow address:0x1".to_string(),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            server.join().unwrap(),
+            "[[BATCH]]dispatch movetoworkspace 3,address:0x1 ; dispatch focuswindow address:0x1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_socket_fails_to_connect() {
+        let dir = tempdir().unwrap();
+        let executor = SocketExecutor::new(dir.path().join(".socket.sock"));
+
+        assert!(executor.execute_json("clients").await.is_err());
+    }
+}
@@ -0,0 +1,531 @@
+//! Resolves the tray icon of a window class from the installed `.desktop` files.
+use crate::pixmap::{self, Pixmap};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// What a `.desktop` file tells about the application it launches.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct DesktopEntry {
+    /// The desktop file id: its path under `applications`, with `-` for `/` and
+    /// without the `.desktop` extension.
+    id: String,
+    icon: String,
+    startup_wm_class: Option<String>,
+    /// The name of the program in the `Exec` line.
+    executable: Option<String>,
+}
+
+// Type alias to simplify the complex type of the pixmap cache, keyed by icon and label.
+type PixmapCache = HashMap<(String, String), Vec<Pixmap>>;
+
+// The sizes of the hicolor theme directories looked into for PNG icons, largest first.
+const THEME_SIZES: [u32; 10] = [512, 256, 128, 96, 64, 48, 32, 24, 22, 16];
+
+/// Finds the icon name of a window class, trying in order the configured overrides,
+/// the `.desktop` files of the XDG application directories, then the class itself.
+#[derive(Debug, Clone)]
+pub struct IconResolver {
+    overrides: HashMap<String, String>,
+    // The XDG data directories, most important first.
+    data_dirs: Vec<PathBuf>,
+    // The entries of the `.desktop` files, read on the first lookup.
+    entries: Arc<OnceLock<Vec<DesktopEntry>>>,
+    // The directories of every installed icon theme, read on the first lookup.
+    theme_dirs: Arc<OnceLock<Vec<PathBuf>>>,
+    // The icon found for each class so far, shared by the clones of the resolver.
+    cache: Arc<Mutex<HashMap<String, String>>>,
+    // The pixmaps rendered for each icon and label so far.
+    pixmaps: Arc<Mutex<PixmapCache>>,
+}
+
+impl Default for IconResolver {
+    fn default() -> Self {
+        IconResolver::new(HashMap::new())
+    }
+}
+
+impl IconResolver {
+    pub fn new(overrides: HashMap<String, String>) -> Self {
+        IconResolver {
+            overrides,
+            data_dirs: data_dirs(),
+            entries: Arc::new(OnceLock::new()),
+            theme_dirs: Arc::new(OnceLock::new()),
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            pixmaps: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The icon name to show for windows of `class`.
+    pub fn resolve(&self, class: &str) -> String {
+        if let Some(icon) = self.overrides.get(class) {
+            return icon.clone();
+        }
+        if let Some(icon) = self.cache.lock().unwrap().get(class) {
+            return icon.clone();
+        }
+        let entries = self.entries.get_or_init(|| {
+            let application_dirs: Vec<PathBuf> = self
+                .data_dirs
+                .iter()
+                .map(|dir| dir.join("applications"))
+                .collect();
+            desktop_entries(&application_dirs)
+        });
+        let icon = find_icon(entries, class).unwrap_or_else(|| class.to_string());
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(class.to_string(), icon.clone());
+        icon
+    }
+
+    /// The pixmaps of `icon`, read from its file on disk off the async runtime.
+    pub async fn pixmaps(&self, icon: &str, label: &str) -> Vec<Pixmap> {
+        let key = (icon.to_string(), label.to_string());
+        if let Some(pixmaps) = self.pixmaps.lock().unwrap().get(&key) {
+            return pixmaps.clone();
+        }
+        let resolver = self.clone();
+        let (icon, label) = key.clone();
+        let pixmaps = tokio::task::spawn_blocking(move || resolver.render(&icon, &label))
+            .await
+            .unwrap_or_else(|e| {
+                eprintln!("[Error] Failed to render the icon {}: {e}", key.0);
+                Vec::new()
+            });
+        self.pixmaps.lock().unwrap().insert(key, pixmaps.clone());
+        pixmaps
+    }
+
+    /// The pixmaps of `icon` read from its file, none when it is only found in an icon
+    /// theme, where hosts look it up by name, or a letter tile made from `label` when
+    /// it is found nowhere.
+    fn render(&self, icon: &str, label: &str) -> Vec<Pixmap> {
+        if let Some(path) = self.icon_file(icon) {
+            return pixmap::load(&path).unwrap_or_default();
+        }
+        if self.is_themed(icon) {
+            return Vec::new();
+        }
+        pixmap::letter_tile(label)
+    }
+
+    /// The file of `icon`: the icon itself when it is an absolute path, else its
+    /// scalable or largest version in the hicolor theme, else the one in `pixmaps`.
+    fn icon_file(&self, icon: &str) -> Option<PathBuf> {
+        if Path::new(icon).is_absolute() {
+            return Some(PathBuf::from(icon)).filter(|path| path.is_file());
+        }
+        let scalable = self
+            .data_dirs
+            .iter()
+            .map(|dir| dir.join(format!("icons/hicolor/scalable/apps/{icon}.svg")));
+        let sized = THEME_SIZES.iter().flat_map(|size| {
+            self.data_dirs
+                .iter()
+                .map(move |dir| dir.join(format!("icons/hicolor/{size}x{size}/apps/{icon}.png")))
+        });
+        let pixmaps = self.data_dirs.iter().flat_map(|dir| {
+            ["png", "svg"].map(|extension| dir.join(format!("pixmaps/{icon}.{extension}")))
+        });
+        scalable
+            .chain(sized)
+            .chain(pixmaps)
+            .find(|path| path.is_file())
+    }
+
+    /// Whether any installed icon theme has `icon`.
+    fn is_themed(&self, icon: &str) -> bool {
+        let theme_dirs = self.theme_dirs.get_or_init(|| {
+            let home_icons = env::var_os("HOME").map(|home| PathBuf::from(home).join(".icons"));
+            let icon_dirs: Vec<PathBuf> = home_icons
+                .into_iter()
+                .chain(self.data_dirs.iter().map(|dir| dir.join("icons")))
+                .collect();
+            theme_dirs(&icon_dirs)
+        });
+        theme_dirs.iter().any(|dir| {
+            ["png", "svg", "xpm"]
+                .iter()
+                .any(|extension| dir.join(format!("{icon}.{extension}")).is_file())
+        })
+    }
+}
+
+/// The directories of the icon themes installed in `icon_dirs`, as listed by the
+/// `Directories` and `ScaledDirectories` of their `index.theme`.
+fn theme_dirs(icon_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for icon_dir in icon_dirs {
+        let Ok(read_dir) = fs::read_dir(icon_dir) else {
+            continue;
+        };
+        for theme in read_dir.flatten().map(|entry| entry.path()) {
+            let Ok(index) = fs::read_to_string(theme.join("index.theme")) else {
+                continue;
+            };
+            let listed = index
+                .lines()
+                .filter_map(|line| line.split_once('='))
+                .filter(|(key, _)| matches!(key.trim(), "Directories" | "ScaledDirectories"))
+                .flat_map(|(_, value)| value.split(','))
+                .map(str::trim)
+                .filter(|dir| !dir.is_empty());
+            dirs.extend(listed.map(|dir| theme.join(dir)));
+        }
+    }
+    dirs
+}
+
+/// `$XDG_DATA_HOME`, then each of `$XDG_DATA_DIRS`, most important first.
+fn data_dirs() -> Vec<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+
+    data_home
+        .into_iter()
+        .chain(
+            data_dirs
+                .split(':')
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from),
+        )
+        .collect()
+}
+
+/// Reads the `.desktop` files of `dirs`. A desktop file id found in several
+/// directories is only read from the first one, which shadows the others.
+fn desktop_entries(dirs: &[PathBuf]) -> Vec<DesktopEntry> {
+    let mut entries = Vec::new();
+    let mut seen = HashSet::new();
+    for dir in dirs {
+        collect_entries(dir, "", &mut entries, &mut seen);
+    }
+    entries
+}
+
+fn collect_entries(
+    dir: &Path,
+    prefix: &str,
+    entries: &mut Vec<DesktopEntry>,
+    seen: &mut HashSet<String>,
+) {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<PathBuf> = read_dir.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+    for path in paths {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if path.is_dir() {
+            collect_entries(&path, &format!("{prefix}{name}-"), entries, seen);
+        } else if let Some(stem) = name.strip_suffix(".desktop") {
+            let id = format!("{prefix}{stem}");
+            if seen.insert(id.clone())
+                && let Ok(content) = fs::read_to_string(&path)
+                && let Some(entry) = parse_desktop_entry(id, &content)
+            {
+                entries.push(entry);
+            }
+        }
+    }
+}
+
+/// Parses the `[Desktop Entry]` group of a `.desktop` file, or returns `None` when it
+/// has no icon or is hidden.
+fn parse_desktop_entry(id: String, content: &str) -> Option<DesktopEntry> {
+    let mut entry = DesktopEntry {
+        id,
+        ..Default::default()
+    };
+    let mut in_group = false;
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_group = line == "[Desktop Entry]";
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if !in_group {
+            continue;
+        }
+        let value = value.trim();
+        match key.trim() {
+            "Icon" => entry.icon = value.to_string(),
+            "StartupWMClass" => entry.startup_wm_class = Some(value.to_string()),
+            "Exec" => entry.executable = executable(value),
+            "Hidden" if value == "true" => return None,
+            _ => {}
+        }
+    }
+    (!entry.icon.is_empty()).then_some(entry)
+}
+
+/// The file name of the program an `Exec` line runs, looking past `env` and its
+/// variables, or the application id of a `flatpak run`.
+fn executable(exec: &str) -> Option<String> {
+    let mut words = exec.split_whitespace().map(|word| word.trim_matches('"'));
+    let file_name = |program: &str| Some(Path::new(program).file_name()?.to_str()?.to_string());
+    let mut program = words.next()?;
+    if file_name(program)? == "env" {
+        program = loop {
+            match words.next()? {
+                // The options taking a value.
+                "-u" | "--unset" | "-C" | "--chdir" => {
+                    words.next();
+                }
+                word if word.starts_with('-') || word.contains('=') => {}
+                word => break word,
+            }
+        };
+    }
+    let name = file_name(program)?;
+    if name == "flatpak" {
+        let mut words = words.skip_while(|word| word.starts_with('-'));
+        if words.next() == Some("run") {
+            return words
+                .find(|word| !word.starts_with('-'))
+                .map(str::to_string);
+        }
+    }
+    Some(name)
+}
+
+/// The icon of the entry matching `class`: by `StartupWMClass`, then by desktop file
+/// id, in full or as the last part of a reverse-DNS id, then by executable name.
+fn find_icon(entries: &[DesktopEntry], class: &str) -> Option<String> {
+    let matches = |value: &str| value.eq_ignore_ascii_case(class);
+    let by_wm_class = |e: &&DesktopEntry| e.startup_wm_class.as_deref().is_some_and(matches);
+    let by_id = |e: &&DesktopEntry| matches(&e.id);
+    let by_id_suffix =
+        |e: &&DesktopEntry| e.id.rsplit_once('.').is_some_and(|(_, last)| matches(last));
+    let by_executable = |e: &&DesktopEntry| e.executable.as_deref().is_some_and(matches);
+
+    entries
+        .iter()
+        .find(by_wm_class)
+        .or_else(|| entries.iter().find(by_id))
+        .or_else(|| entries.iter().find(by_id_suffix))
+        .or_else(|| entries.iter().find(by_executable))
+        .map(|entry| entry.icon.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn resolver_in(overrides: HashMap<String, String>, data_dirs: Vec<PathBuf>) -> IconResolver {
+        IconResolver {
+            data_dirs,
+            ..IconResolver::new(overrides)
+        }
+    }
+
+    fn write_desktop_file(dir: &Path, name: &str, content: &str) {
+        let path = dir.join("applications").join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_parse_desktop_entry() {
+        let content = "\
+[Desktop Entry]
+Name=Firefox
+Icon=firefox
+Icon[de]=firefox-de
+Exec=/usr/lib/firefox/firefox %u
+StartupWMClass=firefox
+
+[Desktop Action new-window]
+Icon=window-new
+";
+        let entry = parse_desktop_entry("org.mozilla.firefox".to_string(), content).unwrap();
+        assert_eq!(
+            entry,
+            DesktopEntry {
+                id: "org.mozilla.firefox".to_string(),
+                icon: "firefox".to_string(),
+                startup_wm_class: Some("firefox".to_string()),
+                executable: Some("firefox".to_string()),
+            }
+        );
+
+        assert!(parse_desktop_entry("no-icon".to_string(), "[Desktop Entry]\nName=X\n").is_none());
+        assert!(
+            parse_desktop_entry(
+                "hidden".to_string(),
+                "[Desktop Entry]\nIcon=x\nHidden=true\n"
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_executable_looks_past_env_and_flatpak() {
+        assert_eq!(
+            executable("\"/opt/Signal/signal\" %U").as_deref(),
+            Some("signal")
+        );
+        assert_eq!(
+            executable("env GDK_BACKEND=x11 -u WAYLAND_DISPLAY /usr/bin/steam %U").as_deref(),
+            Some("steam")
+        );
+        assert_eq!(
+            executable("/usr/bin/flatpak run --branch=stable --arch=x86_64 --command=obs com.obsproject.Studio")
+                .as_deref(),
+            Some("com.obsproject.Studio")
+        );
+        assert_eq!(executable("flatpak list").as_deref(), Some("flatpak"));
+        assert_eq!(executable("env FOO=bar"), None);
+    }
+
+    #[test]
+    fn test_resolve_matches_desktop_files() {
+        let home = TempDir::new().unwrap();
+        let system = TempDir::new().unwrap();
+        write_desktop_file(
+            system.path(),
+            "code.desktop",
+            "[Desktop Entry]\nIcon=vscode\nExec=/usr/share/code/code --unity-launch %F\nStartupWMClass=Code\n",
+        );
+        write_desktop_file(
+            system.path(),
+            "org.gnome.Nautilus.desktop",
+            "[Desktop Entry]\nIcon=org.gnome.Nautilus\nExec=nautilus --new-window\n",
+        );
+        write_desktop_file(
+            system.path(),
+            "kde/org.kde.dolphin.desktop",
+            "[Desktop Entry]\nIcon=system-file-manager\nExec=dolphin %u\n",
+        );
+        write_desktop_file(
+            system.path(),
+            "signal.desktop",
+            "[Desktop Entry]\nIcon=signal-system\nExec=signal-desktop\n",
+        );
+        // A file in a more important directory shadows the one with the same id.
+        write_desktop_file(
+            home.path(),
+            "signal.desktop",
+            "[Desktop Entry]\nIcon=signal-custom\nExec=signal-desktop\n",
+        );
+
+        let resolver = resolver_in(
+            HashMap::new(),
+            vec![home.path().to_path_buf(), system.path().to_path_buf()],
+        );
+
+        assert_eq!(resolver.resolve("code"), "vscode");
+        assert_eq!(resolver.resolve("org.gnome.Nautilus"), "org.gnome.Nautilus");
+        assert_eq!(
+            resolver.resolve("kde-org.kde.dolphin"),
+            "system-file-manager"
+        );
+        assert_eq!(resolver.resolve("dolphin"), "system-file-manager");
+        assert_eq!(resolver.resolve("signal-desktop"), "signal-custom");
+        assert_eq!(resolver.resolve("unknown-app"), "unknown-app");
+    }
+
+    #[test]
+    fn test_resolve_prefers_overrides_and_caches() {
+        let dir = TempDir::new().unwrap();
+        write_desktop_file(
+            dir.path(),
+            "kitty.desktop",
+            "[Desktop Entry]\nIcon=kitty-terminal\n",
+        );
+
+        let overrides = HashMap::from([("steam_app_570".to_string(), "steam".to_string())]);
+        let resolver = resolver_in(overrides, vec![dir.path().to_path_buf()]);
+
+        assert_eq!(resolver.resolve("steam_app_570"), "steam");
+        assert_eq!(resolver.resolve("kitty"), "kitty-terminal");
+
+        // The files are only read once, by the first lookup.
+        fs::remove_file(dir.path().join("applications/kitty.desktop")).unwrap();
+        write_desktop_file(
+            dir.path(),
+            "alacritty.desktop",
+            "[Desktop Entry]\nIcon=alacritty-custom\n",
+        );
+        assert_eq!(resolver.clone().resolve("kitty"), "kitty-terminal");
+        assert_eq!(resolver.resolve("alacritty"), "alacritty");
+    }
+
+    #[tokio::test]
+    async fn test_pixmaps_come_from_icon_files_or_a_letter_tile() {
+        let dir = TempDir::new().unwrap();
+        let png = dir.path().join("icons/hicolor/48x48/apps/firefox.png");
+        fs::create_dir_all(png.parent().unwrap()).unwrap();
+        let mut image = resvg::tiny_skia::Pixmap::new(48, 48).unwrap();
+        image.fill(resvg::tiny_skia::Color::from_rgba8(0, 0, 255, 255));
+        image.save_png(&png).unwrap();
+        // The scalable version wins over the sized ones.
+        let scalable = dir.path().join("icons/hicolor/scalable/apps/firefox.svg");
+        fs::create_dir_all(scalable.parent().unwrap()).unwrap();
+        fs::write(
+            &scalable,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="4">
+                <rect width="4" height="4" fill="red"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let legacy = dir.path().join("pixmaps/steam.png");
+        fs::create_dir_all(legacy.parent().unwrap()).unwrap();
+        fs::copy(&png, &legacy).unwrap();
+
+        let resolver = resolver_in(HashMap::new(), vec![dir.path().to_path_buf()]);
+
+        assert_eq!(resolver.icon_file("firefox"), Some(scalable));
+        assert_eq!(resolver.icon_file("steam"), Some(legacy));
+        assert_eq!(resolver.icon_file(png.to_str().unwrap()), Some(png.clone()));
+        assert_eq!(resolver.icon_file("unknown-app"), None);
+
+        let firefox = resolver.pixmaps("firefox", "firefox").await;
+        assert_eq!(firefox.len(), pixmap::SIZES.len());
+        assert_eq!(&firefox[0].2[..4], &[0xff, 0xff, 0x00, 0x00]);
+        let absolute = resolver.pixmaps(png.to_str().unwrap(), "firefox").await;
+        assert_eq!(&absolute[0].2[..4], &[0xff, 0x00, 0x00, 0xff]);
+        assert_eq!(
+            resolver.pixmaps("unknown-app", "unknown-app").await,
+            pixmap::letter_tile("unknown-app")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_themed_icons_get_no_letter_tile() {
+        let dir = TempDir::new().unwrap();
+        let theme = dir.path().join("icons/Papirus");
+        fs::create_dir_all(theme.join("48x48/apps")).unwrap();
+        fs::write(
+            theme.join("index.theme"),
+            "[Icon Theme]\nName=Papirus\nDirectories=16x16/apps,48x48/apps\n",
+        )
+        .unwrap();
+        fs::write(theme.join("48x48/apps/telegram.svg"), "<svg/>").unwrap();
+
+        let resolver = resolver_in(HashMap::new(), vec![dir.path().to_path_buf()]);
+
+        // Hosts find the icon in the theme by its name.
+        assert!(resolver.pixmaps("telegram", "telegram").await.is_empty());
+        assert_eq!(
+            resolver.pixmaps("unknown-app", "unknown-app").await,
+            pixmap::letter_tile("unknown-app")
+        );
+    }
+}
@@ -1,10 +1,15 @@
 //! Main application entry point for the hyprland-minimize utility.
 mod cli;
 mod config;
+mod control;
+mod daemon;
 mod dbus;
+mod exit;
 mod hyprland;
+mod icons;
 mod menu;
 mod minimize;
+mod pixmap;
 mod restore;
 mod stack;
 
@@ -12,24 +17,36 @@ use anyhow::{Context, Result};
 use clap::Parser;
 use directories::ProjectDirs;
 use figment::{
-    providers::{Format, Serialized, Toml},
     Figment,
+    providers::{Format, Serialized, Toml},
 };
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::process::ExitCode;
+use std::time::Duration;
 
-use crate::cli::Args;
-use crate::config::{generate_default_config, get_config_dir, Config};
-use crate::hyprland::{Hyprland, LiveExecutor};
+use crate::cli::{Args, Commands};
+use crate::config::{Config, generate_default_config, get_config_dir};
+use crate::daemon::Daemon;
+use crate::hyprland::socket::instance_signature;
+use crate::hyprland::{CallPolicy, Hyprland, executor_for};
+use crate::icons::IconResolver;
 use crate::menu::Menu;
 use crate::minimize::{LiveDbus, Minimizer};
 use crate::restore::restore_last_minimized;
 use crate::stack::Stack;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+async fn main() -> ExitCode {
+    match run(Args::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::from(exit::code(&e))
+        }
+    }
+}
 
+async fn run(args: Args) -> Result<()> {
     // Check for the generate_config_file flag first.
     if args.generate_config_file {
         // If the flag is present, generate the file and exit.
@@ -54,11 +71,30 @@ async fn main() -> Result<()> {
         // 3. Merge CLI arguments, which have the highest priority
         .merge(Serialized::defaults(args.clone()))
         .extract()
-        .expect("Failed to load configuration");
+        .with_context(|| format!("Failed to load the configuration from {:?}", config_path))?;
 
-    let hyprland = Hyprland::new(Arc::new(LiveExecutor));
-    let stack = Stack::at_default_path(config.clone())
-        .expect("Failed to initialize the application stack. Ensure $USER is set.");
+    // Resolve the instance once, so every part of the program targets the same one.
+    // Only an instance given on the command line has to resolve.
+    let instance = match args.instance.as_deref() {
+        Some(instance) => Some(instance_signature(Some(instance))?),
+        None => instance_signature(None).ok(),
+    };
+    let hyprland = Hyprland::new(executor_for(
+        config.ipc_backend.unwrap(),
+        instance.as_deref(),
+    ))
+    .with_instance(instance.clone())
+    .with_call_policy(CallPolicy {
+        timeout: Duration::from_millis(config.ipc_timeout_ms.unwrap()),
+        retries: config.ipc_retries.unwrap(),
+        ..CallPolicy::default()
+    });
+    let stack = Stack::at_default_path(config.clone(), instance.as_deref())
+        .context("Failed to initialize the application stack.")?;
+
+    if args.command == Some(Commands::Daemon) {
+        return Daemon::run(config, stack, hyprland).await;
+    }
 
     if args.menu {
         let menu = Menu::new(&config, &stack, &hyprland);
@@ -66,17 +102,29 @@ async fn main() -> Result<()> {
     }
 
     if args.restore_last {
+        if let Some(daemon) = daemon::connect(instance.as_deref()).await {
+            return daemon
+                .restore_last()
+                .await
+                .context("The daemon failed to restore the last window.");
+        }
         return restore_last_minimized(config.clone(), &stack, &hyprland).await;
     }
 
     let window_info = if let Some(address) = args.window_address {
-        hyprland.get_window_by_address(&address)?
+        hyprland.get_window_by_address(&address).await?
     } else {
-        hyprland
-            .exec("activewindow")
-            .context("Failed to get active window. Is a window focused?")?
+        hyprland.active_window().await?
     };
 
-    let minimizer = Minimizer::new(config.clone(), &stack, window_info, hyprland, &LiveDbus);
+    if let Some(daemon) = daemon::connect(instance.as_deref()).await {
+        return daemon
+            .minimize(&window_info.address)
+            .await
+            .context("The daemon failed to minimize the window.");
+    }
+
+    let dbus = LiveDbus::new(IconResolver::new(config.icon_overrides.clone().unwrap()));
+    let minimizer = Minimizer::new(config.clone(), &stack, window_info, hyprland, &dbus);
     minimizer.minimize().await
 }
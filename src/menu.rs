@@ -1,11 +1,22 @@
 //! Handles the interactive window selection logic using a dmenu-style launcher.
 use crate::config::Config;
-use crate::hyprland::{Hyprland, WindowInfo, Workspace};
-use crate::stack::Stack;
+use crate::hyprland::Hyprland;
+use crate::restore::restore_to_outside;
+use crate::stack::{Stack, StackEntry, StackError};
 
 use anyhow::{Context, Result};
 use std::io::{Read, Write};
 use std::process::{Command, Stdio};
+use thiserror::Error;
+
+/// The failures of the selection menu that callers may want to tell apart.
+#[derive(Debug, Error)]
+pub enum MenuError {
+    #[error("No window selected.")]
+    Cancelled,
+    #[error("Could not parse window address from selection: '{0}'")]
+    InvalidSelection(String),
+}
 
 /// A struct to manage the interactive window selection menu.
 pub struct Menu<'a> {
@@ -26,10 +37,9 @@ impl<'a> Menu<'a> {
 
     /// Presents a list of minimized windows to the user and restores the selected one.
     pub async fn show_and_restore(&self) -> Result<()> {
-        let windows = self.stack.minimized(self.hyprland)?;
+        let windows = self.stack.minimized(self.hyprland).await?;
         if windows.is_empty() {
-            println!("No windows to restore.");
-            return Ok(());
+            return Err(StackError::Empty.into());
         }
 
         let choices = windows
@@ -40,24 +50,16 @@ impl<'a> Menu<'a> {
 
         let selection = self.run_launcher(&choices)?;
         if selection.is_empty() {
-            println!("No window selected.");
-            return Ok(());
+            return Err(MenuError::Cancelled.into());
         }
 
         // Parse the address from the selection string "Title (Address)".
-        if let Some(address) = self.parse_address_from_selection(&selection) {
-            if let Some(selected_window) = windows.into_iter().find(|w| w.address == address) {
-                self.restore_selected_window(&selected_window)?;
-                println!("Restored window: {}", selected_window.title);
-            } else {
-                println!("No window selected or selection was invalid.");
-            }
-        } else {
-            println!(
-                "Could not parse window address from selection: '{}'",
-                selection
-            );
-        }
+        let selected_window = self
+            .parse_address_from_selection(&selection)
+            .and_then(|address| windows.into_iter().find(|w| w.address == address))
+            .ok_or(MenuError::InvalidSelection(selection))?;
+        self.restore_selected_window(&selected_window).await?;
+        println!("Restored window: {}", selected_window.title);
 
         Ok(())
     }
@@ -66,7 +68,7 @@ impl<'a> Menu<'a> {
     fn run_launcher(&self, choices: &str) -> Result<String> {
         let mut child = Command::new("sh")
             .arg("-c")
-            .arg(&self.config.launcher.clone().unwrap())
+            .arg(self.config.launcher.clone().unwrap())
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .spawn()
@@ -91,22 +93,24 @@ impl<'a> Menu<'a> {
         }
 
         let status = child.wait().context("Launcher command failed to run")?;
+        // Launchers exit with a failure when dismissed without a selection.
         if !status.success() {
-            return Ok(String::new());
+            return Err(MenuError::Cancelled.into());
         }
 
         Ok(output.trim().to_string())
     }
 
-    /// Restores the selected window to the active workspace and removes it from the stack.
-    fn restore_selected_window(&self, window: &WindowInfo) -> Result<()> {
-        let active_workspace: Workspace = self.hyprland.exec("activeworkspace")?;
-        self.hyprland.dispatch(&format!(
-            "movetoworkspace {},address:{}",
-            active_workspace.id, window.address
-        ))?;
-        self.hyprland
-            .dispatch(&format!("focuswindow address:{}", window.address))?;
+    /// Restores the selected window to the configured `restore_to` target and removes it
+    /// from the stack.
+    async fn restore_selected_window(&self, window: &StackEntry) -> Result<()> {
+        restore_to_outside(
+            self.hyprland,
+            window,
+            self.config.restore_to.unwrap(),
+            self.config.workspace.as_deref().unwrap(),
+        )
+        .await?;
         self.stack.remove(&window.address)
     }
 
@@ -122,7 +126,8 @@ impl<'a> Menu<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::hyprland::{self, Workspace};
+    use crate::hyprland;
+    use async_trait::async_trait;
     use std::os::unix::process::ExitStatusExt;
     use std::process::{ExitStatus, Output};
     use std::sync::{Arc, Mutex};
@@ -132,6 +137,7 @@ mod tests {
     #[derive(Default, Clone)]
     struct MockExecutor {
         dispatched_commands: Arc<Mutex<Vec<String>>>,
+        dispatched_batches: Arc<Mutex<Vec<Vec<String>>>>,
         json_responses: Arc<Mutex<Vec<String>>>,
     }
     impl MockExecutor {
@@ -141,9 +147,13 @@ mod tests {
         fn dispatched_commands(&self) -> Vec<String> {
             self.dispatched_commands.lock().unwrap().clone()
         }
+        fn dispatched_batches(&self) -> Vec<Vec<String>> {
+            self.dispatched_batches.lock().unwrap().clone()
+        }
     }
+    #[async_trait]
     impl hyprland::HyprctlExecutor for MockExecutor {
-        fn execute_json(&self, _command: &str) -> Result<Output> {
+        async fn execute_json(&self, _command: &str) -> Result<Output> {
             let response = self
                 .json_responses
                 .lock()
@@ -156,7 +166,7 @@ mod tests {
                 stderr: vec![],
             })
         }
-        fn execute_dispatch(&self, command: &str) -> Result<Output> {
+        async fn execute_dispatch(&self, command: &str) -> Result<Output> {
             self.dispatched_commands
                 .lock()
                 .unwrap()
@@ -167,6 +177,17 @@ mod tests {
                 stderr: vec![],
             })
         }
+        async fn execute_batch(&self, commands: &[String]) -> Result<Output> {
+            self.dispatched_batches
+                .lock()
+                .unwrap()
+                .push(commands.to_vec());
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: vec![],
+                stderr: vec![],
+            })
+        }
     }
 
     #[test]
@@ -192,8 +213,8 @@ mod tests {
         );
     }
 
-    #[test]
-    fn test_restore_selected_window() -> Result<()> {
+    #[tokio::test]
+    async fn test_restore_selected_window() -> Result<()> {
         // --- Setup ---
         let temp_file = NamedTempFile::new()?;
         let stack = Stack::new(temp_file.path());
@@ -202,25 +223,67 @@ mod tests {
         let hyprland = Hyprland::new(mock_executor.clone());
         let menu = Menu::new(&config, &stack, &hyprland);
 
-        let window_to_restore = WindowInfo {
+        let window_to_restore = StackEntry {
             address: "0xRESTORE".to_string(),
             title: "Test".to_string(),
             class: "Test".to_string(),
-            workspace: Workspace { id: 1 },
+            workspace_id: 1,
+            ..Default::default()
         };
 
         // Mock the hyprland response for `activeworkspace`
         mock_executor.add_json_response(r#"{"id": 5}"#);
 
         // --- Execute ---
-        menu.restore_selected_window(&window_to_restore)?;
+        menu.restore_selected_window(&window_to_restore).await?;
 
         // --- Assert ---
-        let dispatched = mock_executor.dispatched_commands();
-        assert_eq!(dispatched.len(), 2);
-        assert_eq!(dispatched[0], "movetoworkspace 5,address:0xRESTORE");
-        assert_eq!(dispatched[1], "focuswindow address:0xRESTORE");
+        assert_eq!(
+            mock_executor.dispatched_batches(),
+            [[
+                "movetoworkspace 5,address:0xRESTORE",
+                "focuswindow address:0xRESTORE"
+            ]]
+        );
+        assert!(mock_executor.dispatched_commands().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dismissed_launcher_is_cancelled() {
+        let config = Config {
+            launcher: Some("cat > /dev/null; exit 1".to_string()),
+            ..Config::default()
+        };
+        let stack = Stack::new("");
+        let hyprland = Hyprland::new(Arc::new(MockExecutor::default()));
+        let menu = Menu::new(&config, &stack, &hyprland);
+
+        let error = menu.run_launcher("Title (0x1)").unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<MenuError>(),
+            Some(MenuError::Cancelled)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_nothing_to_restore_is_stack_empty() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let stack = Stack::new(temp_file.path());
+        let config = Config::default();
+        let mock_executor = Arc::new(MockExecutor::default());
+        mock_executor.add_json_response("[]");
+        let hyprland = Hyprland::new(mock_executor);
+        let menu = Menu::new(&config, &stack, &hyprland);
+
+        let error = menu.show_and_restore().await.unwrap_err();
 
+        assert!(matches!(
+            error.downcast_ref::<StackError>(),
+            Some(StackError::Empty)
+        ));
         Ok(())
     }
 }
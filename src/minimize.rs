@@ -1,17 +1,55 @@
 //! Contains the core logic for minimizing a window to a tray icon.
-use crate::cli::RestoreTarget;
 use crate::config::Config;
 use crate::dbus::{DbusMenu, StatusNotifierItem};
-use crate::hyprland::{Hyprland, WindowInfo, Workspace};
-use crate::stack::Stack;
+use crate::hyprland::events::Event;
+use crate::hyprland::socket::event_socket_path;
+use crate::hyprland::{Hyprland, WindowInfo, Workspace, is_unreachable};
+use crate::icons::IconResolver;
+use crate::restore::{rejoin_group, restore_into, target_workspace};
+use crate::stack::{Stack, StackEntry};
 
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use futures_util::stream::StreamExt;
 use std::sync::Arc;
+use thiserror::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+use tokio::net::UnixStream;
+use tokio::signal::unix::{SignalKind, signal};
 use tokio::sync::Notify;
+use tokio::task::JoinHandle;
 use tokio::time::{interval, Duration};
-use zbus::{Connection, ConnectionBuilder, Proxy};
+use zbus::{Connection, ConnectionBuilder, Proxy, fdo};
+
+/// The failures of minimizing a window that callers may want to tell apart.
+#[derive(Debug, Error)]
+pub enum MinimizeError {
+    #[error(
+        "No tray is running to show the icon in: org.kde.StatusNotifierWatcher is not on the bus."
+    )]
+    NoTrayWatcher,
+}
+
+/// Resolves on Ctrl+C, or on SIGTERM, which service managers such as systemd stop
+/// processes with.
+pub async fn shutdown_signal() -> Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        _ = terminate.recv() => {}
+    }
+    Ok(())
+}
+
+/// The window as it should be restored: with the state it has now, or without it
+/// when its class opted out with `preserve_state_exclude`.
+pub fn restorable(config: &Config, window: &WindowInfo) -> WindowInfo {
+    if config.preserves_state_of(&window.class) {
+        window.clone()
+    } else {
+        window.without_state()
+    }
+}
 
 // D-Bus Connection, mockable
 #[async_trait]
@@ -23,10 +61,24 @@ pub trait DbusConnection: Send + Sync {
         hyprland: &Hyprland,
     ) -> Result<Option<(Arc<Connection>, String)>>;
     async fn register(&self, connection: &Arc<Connection>, bus_name: &str) -> Result<()>;
+    /// Releases what `setup` created once the window is no longer minimized. A
+    /// per-process connection is simply dropped on exit, so there is nothing to do.
+    async fn teardown(&self, _connection: &Arc<Connection>, _bus_name: &str) -> Result<()> {
+        Ok(())
+    }
 }
 
 // Real instance of D-Bus
-pub struct LiveDbus;
+pub struct LiveDbus {
+    icons: IconResolver,
+}
+
+impl LiveDbus {
+    pub fn new(icons: IconResolver) -> Self {
+        LiveDbus { icons }
+    }
+}
+
 #[async_trait]
 impl DbusConnection for LiveDbus {
     async fn setup(
@@ -36,7 +88,7 @@ impl DbusConnection for LiveDbus {
         hyprland: &Hyprland,
     ) -> Result<Option<(Arc<Connection>, String)>> {
         Ok(Some(
-            setup_dbus_connection(window_info, exit_notify, hyprland).await?,
+            setup_dbus_connection(window_info, &self.icons, exit_notify, hyprland).await?,
         ))
     }
     async fn register(&self, connection: &Arc<Connection>, bus_name: &str) -> Result<()> {
@@ -45,15 +97,19 @@ impl DbusConnection for LiveDbus {
 }
 
 // Minimizer service
-pub struct Minimizer<'a, D: DbusConnection> {
+pub struct Minimizer<'a, D: DbusConnection + ?Sized> {
     config: Config,
     stack: &'a Stack,
     window_info: WindowInfo,
+    /// The window as it will be restored: without its state if its class opted out.
+    snapshot: WindowInfo,
+    /// The rest of its group, when the whole group is minimized with it.
+    members: Vec<String>,
     hyprland: Hyprland,
     dbus: &'a D,
 }
 
-impl<'a, D: DbusConnection> Minimizer<'a, D> {
+impl<'a, D: DbusConnection + ?Sized> Minimizer<'a, D> {
     pub fn new(
         config: Config,
         stack: &'a Stack,
@@ -61,7 +117,19 @@ impl<'a, D: DbusConnection> Minimizer<'a, D> {
         hyprland: Hyprland,
         dbus: &'a D,
     ) -> Self {
+        let members = if config.minimize_whole_group.unwrap() {
+            window_info
+                .grouped
+                .iter()
+                .filter(|member| **member != window_info.address)
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        };
         Minimizer {
+            snapshot: restorable(&config, &window_info),
+            members,
             config,
             stack,
             window_info,
@@ -71,82 +139,153 @@ impl<'a, D: DbusConnection> Minimizer<'a, D> {
     }
 
     pub async fn minimize(self) -> Result<()> {
-        self.minimize_window()?;
+        self.hide().await?.wait().await
+    }
 
-        let exit_notify = Arc::new(Notify::new());
+    /// Records the window in the stack, moves it to the minimized workspace and
+    /// registers its tray icon. When any of it fails, the window is put back and the
+    /// error returned.
+    pub async fn hide(self) -> Result<MinimizedWindow<'a, D>> {
+        self.minimize_window().await?;
 
-        let dbus_result = self.setup_and_register_dbus(Arc::clone(&exit_notify)).await;
+        let exit_notify = Arc::new(Notify::new());
 
-        if let Err(e) = dbus_result {
-            self.restore_window()?;
-            return Err(e);
+        match self.setup_and_register_dbus(Arc::clone(&exit_notify)).await {
+            Ok((connection, bus_name)) => Ok(MinimizedWindow {
+                minimizer: self,
+                exit_notify,
+                connection,
+                bus_name,
+            }),
+            Err(e) => {
+                if let Err(e) = self.restore_window().await {
+                    eprintln!("[Error] Failed to restore window: {e}");
+                }
+                if let Err(e) = self.restore_members().await {
+                    eprintln!("[Error] Failed to restore the rest of the group: {e}");
+                }
+                Err(e)
+            }
         }
+    }
 
-        let (arc_conn, bus_name) = dbus_result.unwrap();
-
-        spawn_background_tasks(
-            arc_conn,
-            bus_name,
-            self.window_info.address.clone(),
-            Arc::clone(&exit_notify),
-            self.hyprland.clone(),
-            self.config.poll_interval_ms.unwrap(),
-            self.config.auto_unminimize_on_focus.unwrap(),
+    async fn minimize_window(&self) -> Result<()> {
+        println!(
+            "Minimizing window: '{}' ({}) from workspace {}",
+            self.window_info.title, self.window_info.class, self.window_info.workspace.id
         );
+        self.stack.push(StackEntry::from_window(&self.snapshot))?;
+        let result = self.move_to_minimized_workspace().await;
+        if result.is_err() {
+            // The window did not move, so it is not minimized.
+            let _ = self.stack.remove(&self.window_info.address);
+        }
+        result
+    }
 
-        println!("Application minimized to tray. Waiting for activation...");
-        self.await_exit_signal(exit_notify).await;
-
-        if let Err(e) = self.stack.remove(&self.window_info.address) {
-            eprintln!("[Error] Failed to remove window from stack file: {e}");
+    async fn move_to_minimized_workspace(&self) -> Result<()> {
+        let minimized_workspace = self.config.workspace.clone().unwrap();
+        let mut commands = Vec::new();
+        // Decided from the snapshot, which is what restoring pins back from.
+        if self.snapshot.pinned {
+            // A pinned window shows on every workspace, so it has to be unpinned to hide.
+            commands.push(format!("pin address:{}", self.window_info.address));
         }
-        println!("Exiting.");
+        for address in std::iter::once(&self.window_info.address).chain(&self.members) {
+            commands.push(format!(
+                "movetoworkspacesilent {minimized_workspace},address:{address}"
+            ));
+        }
+        match commands.as_slice() {
+            [command] => self.hyprland.dispatch(command).await,
+            _ => self.hyprland.dispatch_batch(&commands).await,
+        }
+    }
 
+    /// Brings back the rest of a group minimized as a unit, next to the window, or to
+    /// the workspace it was minimized from when the window was closed.
+    async fn restore_members(&self) -> Result<()> {
+        if self.members.is_empty() {
+            return Ok(());
+        }
+        let workspace = match self
+            .hyprland
+            .get_window_by_address(&self.window_info.address)
+            .await
+        {
+            Ok(window) if Some(&window.workspace.name) != self.config.workspace.as_ref() => {
+                Some(window.workspace)
+            }
+            _ => None,
+        };
+        for member in &self.members {
+            // Members restored or closed meanwhile are left alone.
+            let Ok(window) = self.hyprland.get_window_by_address(member).await else {
+                continue;
+            };
+            if Some(&window.workspace.name) != self.config.workspace.as_ref() {
+                continue;
+            }
+            let target = workspace
+                .clone()
+                .unwrap_or_else(|| self.original_workspace());
+            self.hyprland
+                .dispatch(&format!(
+                    "movetoworkspacesilent {},address:{member}",
+                    target.dispatch_target()
+                ))
+                .await?;
+            rejoin_group(&self.hyprland, member, &self.window_info.grouped).await?;
+        }
+        if workspace.is_some() {
+            self.hyprland
+                .dispatch(&format!("focuswindow address:{}", self.window_info.address))
+                .await?;
+        }
         Ok(())
     }
 
-    fn minimize_window(&self) -> Result<()> {
-        println!(
-            "Minimizing window: '{}' ({}) from workspace {}",
-            self.window_info.title, self.window_info.class, self.window_info.workspace.id
-        );
-        self.stack.push(&self.window_info.address)?;
-        self.hyprland.dispatch(&format!(
-            "movetoworkspacesilent {},address:{}",
-            self.config.workspace.clone().unwrap(),
-            self.window_info.address
-        ))
+    /// The workspace the window was minimized from.
+    fn original_workspace(&self) -> Workspace {
+        Workspace {
+            monitor_id: Some(self.window_info.monitor),
+            ..self.window_info.workspace.clone()
+        }
     }
 
-    fn restore_window(&self) -> Result<()> {
+    async fn restore_window(&self) -> Result<()> {
+        let entry = StackEntry::from_window(&self.snapshot);
         // Default to restoring to the window's original workspace.
-        let mut target_workspace_id = self.window_info.workspace.id;
+        let mut workspace = self.original_workspace();
 
-        // If configured to restore to active, try to get it, but only use it if it's a regular workspace.
-        if self.config.restore_to.unwrap() == RestoreTarget::Active {
-            if let Ok(active_ws) = self.hyprland.exec::<Workspace>("activeworkspace") {
-                if active_ws.id > 0 {
-                    target_workspace_id = active_ws.id;
-                }
-            }
+        // Use the configured target when it can be resolved, but never restore into
+        // the minimized workspace itself.
+        if let Ok(target) =
+            target_workspace(&self.hyprland, &entry, self.config.restore_to.unwrap()).await
+            && Some(&target.name) != self.config.workspace.as_ref()
+        {
+            workspace = target;
         }
 
-        self.hyprland.dispatch(&format!(
-            "movetoworkspace {},address:{}",
-            target_workspace_id, self.window_info.address
-        ))?;
-        self.hyprland
-            .dispatch(&format!("focuswindow address:{}", self.window_info.address))?;
+        restore_into(&self.hyprland, &entry, Some(&workspace)).await?;
         self.stack.remove(&self.window_info.address)
     }
 
+    /// Checks whether the window still exists and sits in the minimized workspace.
+    async fn is_still_minimized(&self) -> bool {
+        self.hyprland
+            .get_window_by_address(&self.window_info.address)
+            .await
+            .is_ok_and(|window| Some(&window.workspace.name) == self.config.workspace.as_ref())
+    }
+
     async fn setup_and_register_dbus(
         &self,
         exit_notify: Arc<Notify>,
     ) -> Result<(Arc<Connection>, String)> {
         let (arc_conn, bus_name) = match self
             .dbus
-            .setup(&self.window_info, exit_notify, &self.hyprland)
+            .setup(&self.snapshot, exit_notify, &self.hyprland)
             .await?
         {
             Some(conn) => conn,
@@ -163,19 +302,17 @@ impl<'a, D: DbusConnection> Minimizer<'a, D> {
 
     async fn await_exit_signal(&self, exit_notify: Arc<Notify>) {
         tokio::select! {
-            _ = tokio::signal::ctrl_c() => {
-                println!("\nInterrupted by Ctrl+C. Restoring window.");
-                let _ = self.hyprland.dispatch(&format!(
-                    "movetoworkspace {},address:{}",
-                    self.window_info.workspace.id,
-                    self.window_info.address,
-                ));
+            Ok(()) = shutdown_signal() => {
+                println!("\nInterrupted. Restoring window.");
+                let entry = StackEntry::from_window(&self.snapshot);
+                let _ = restore_into(&self.hyprland, &entry, Some(&self.original_workspace())).await;
             }
             _ = exit_notify.notified() => {
                 println!("Exit notification received.");
-                // The notification means our job is done. Attempt to restore the window
-                // as the final action, handling any potential errors gracefully.
-                if let Err(e) = self.restore_window() {
+                // The notification means our job is done. Unless the window was already
+                // restored elsewhere or closed, restore it as the final action, handling
+                // any potential errors gracefully.
+                if self.is_still_minimized().await && let Err(e) = self.restore_window().await {
                     eprintln!("[Error] Failed to restore window on exit: {e}");
                 }
             }
@@ -183,8 +320,64 @@ impl<'a, D: DbusConnection> Minimizer<'a, D> {
     }
 }
 
+/// A window moved to the minimized workspace, with its tray icon registered.
+pub struct MinimizedWindow<'a, D: DbusConnection + ?Sized> {
+    minimizer: Minimizer<'a, D>,
+    exit_notify: Arc<Notify>,
+    connection: Arc<Connection>,
+    bus_name: String,
+}
+
+impl<D: DbusConnection + ?Sized> MinimizedWindow<'_, D> {
+    /// Watches the window until it is restored, closed or activated from the tray,
+    /// then removes its tray icon and stack entry.
+    pub async fn wait(self) -> Result<()> {
+        let minimizer = &self.minimizer;
+        let watched_window = WatchedWindow {
+            address: minimizer.window_info.address.clone(),
+            minimized_workspace: minimizer.config.workspace.clone().unwrap(),
+            poll_interval: minimizer.config.poll_interval_ms.unwrap(),
+            auto_unminimize_on_focus: minimizer.config.auto_unminimize_on_focus.unwrap(),
+        };
+        let background_tasks = spawn_background_tasks(
+            Arc::clone(&self.connection),
+            self.bus_name.clone(),
+            watched_window,
+            Arc::clone(&self.exit_notify),
+            minimizer.hyprland.clone(),
+        );
+
+        println!("Application minimized to tray. Waiting for activation...");
+        minimizer
+            .await_exit_signal(Arc::clone(&self.exit_notify))
+            .await;
+
+        for task in background_tasks {
+            task.abort();
+        }
+        if let Err(e) = minimizer
+            .dbus
+            .teardown(&self.connection, &self.bus_name)
+            .await
+        {
+            eprintln!("[Error] Failed to remove tray icon: {e}");
+        }
+
+        if let Err(e) = minimizer.stack.remove(&minimizer.window_info.address) {
+            eprintln!("[Error] Failed to remove window from stack file: {e}");
+        }
+        if let Err(e) = minimizer.restore_members().await {
+            eprintln!("[Error] Failed to restore the rest of the group: {e}");
+        }
+        println!("Exiting.");
+
+        Ok(())
+    }
+}
+
 async fn setup_dbus_connection(
     window_info: &WindowInfo,
+    icons: &IconResolver,
     exit_notify: Arc<Notify>,
     hyprland: &Hyprland,
 ) -> Result<(Arc<Connection>, String)> {
@@ -193,11 +386,14 @@ async fn setup_dbus_connection(
         std::process::id()
     );
 
+    let icon_name = icons.resolve(&window_info.class);
     let notifier_item = StatusNotifierItem::new(
         window_info.clone(),
         Arc::clone(&exit_notify),
         hyprland.clone(),
-    );
+    )
+    .with_icon_pixmap(icons.pixmaps(&icon_name, &window_info.class).await)
+    .with_icon_name(icon_name);
     let dbus_menu = DbusMenu::new(window_info.clone(), Arc::clone(&exit_notify), hyprland);
 
     let connection = ConnectionBuilder::session()?
@@ -210,36 +406,45 @@ async fn setup_dbus_connection(
     Ok((Arc::new(connection), bus_name))
 }
 
-async fn register_with_watcher(connection: &Arc<Connection>, bus_name: &str) -> Result<()> {
+pub async fn register_with_watcher(connection: &Arc<Connection>, bus_name: &str) -> Result<()> {
     let watcher_proxy: Proxy<'_> = zbus::ProxyBuilder::new_bare(connection)
         .interface("org.kde.StatusNotifierWatcher")?
         .path("/StatusNotifierWatcher")?
         .destination("org.kde.StatusNotifierWatcher")?
         .build()
         .await?;
-    watcher_proxy
+    match watcher_proxy
         .call_method("RegisterStatusNotifierItem", &(bus_name,))
-        .await?;
-    Ok(())
+        .await
+        .map_err(fdo::Error::from)
+    {
+        Ok(_) => Ok(()),
+        Err(fdo::Error::ServiceUnknown(_) | fdo::Error::NameHasNoOwner(_)) => {
+            Err(MinimizeError::NoTrayWatcher.into())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// What the background watchers need to know about the minimized window.
+struct WatchedWindow {
+    address: String,
+    minimized_workspace: String,
+    poll_interval: u64,
+    auto_unminimize_on_focus: bool,
 }
 
 fn spawn_background_tasks(
     arc_conn: Arc<Connection>,
     bus_name: String,
-    window_address: String,
+    window: WatchedWindow,
     exit_notify: Arc<Notify>,
     hyprland: Hyprland,
-    poll_interval: u64,
-    auto_unminize_on_focus: bool,
-) {
-    tokio::spawn(watch_for_tray_restarts(arc_conn.clone(), bus_name));
-    tokio::spawn(poll_window_state(
-        poll_interval,
-        auto_unminize_on_focus,
-        window_address,
-        exit_notify,
-        hyprland,
-    ));
+) -> [JoinHandle<()>; 2] {
+    [
+        tokio::spawn(watch_for_tray_restarts(arc_conn, bus_name)),
+        tokio::spawn(watch_window_state(window, exit_notify, hyprland)),
+    ]
 }
 
 /// A background task that re-registers the tray icon if the tray restarts.
@@ -260,74 +465,147 @@ async fn watch_for_tray_restarts(arc_conn: Arc<Connection>, bus_name: String) {
     }
 }
 
-/// A background task that polls hyprland to see if the minimized window
-/// has been closed or restored externally.
-async fn poll_window_state(
-    poll_interval: u64,
-    auto_unminimize_on_focus: bool,
-    window_address: String,
+/// A background task that follows Hyprland's event socket to see if the minimized
+/// window has been closed, restored or focused. Falls back to polling when the
+/// event socket cannot be opened.
+async fn watch_window_state(window: WatchedWindow, exit_notify: Arc<Notify>, hyprland: Hyprland) {
+    let stream = match event_socket_path(hyprland.instance()) {
+        Ok(path) => UnixStream::connect(path).await.ok(),
+        Err(_) => None,
+    };
+
+    match stream {
+        Some(stream) => {
+            watch_window_events(BufReader::new(stream), window, exit_notify, hyprland).await
+        }
+        None => poll_window_state(window, exit_notify, hyprland).await,
+    }
+}
+
+/// Reacts to the Hyprland events concerning the minimized window.
+async fn watch_window_events<R: AsyncBufRead + Unpin>(
+    events: R,
+    window: WatchedWindow,
     exit_notify: Arc<Notify>,
     hyprland: Hyprland,
 ) {
-    let mut interval = interval(Duration::from_millis(poll_interval));
-    loop {
-        interval.tick().await;
+    // The window may have changed before we subscribed, so check once up front.
+    if has_left_minimized_state(&window, &hyprland).await {
+        exit_notify.notify_one();
+        return;
+    }
 
-        // First, check if the window was closed or restored normally.
-        let Ok(clients) = hyprland.exec::<Vec<WindowInfo>>("clients") else {
+    let mut lines = events.lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let should_exit = match Event::parse(&line) {
+            Some(Event::CloseWindow { address }) => address == window.address,
+            Some(Event::MoveWindow {
+                address,
+                workspace_name,
+                ..
+            }) => address == window.address && workspace_name != window.minimized_workspace,
+            Some(Event::ActiveWindow { address }) => {
+                window.auto_unminimize_on_focus && address == window.address
+            }
+            // A workspace switch can reveal the window without a move event, e.g. when
+            // the special workspace is toggled, so verify the state directly.
+            Some(Event::Workspace { .. }) => has_left_minimized_state(&window, &hyprland).await,
+            None => false,
+        };
+
+        if should_exit {
             exit_notify.notify_one();
             return;
-        };
+        }
+    }
 
-        let should_exit = match clients.iter().find(|c| c.address == window_address) {
-            // Window is found, exit if it's been restored to a normal workspace.
-            Some(client) => client.workspace.id > 0,
-            // Window is not found, exit because it has been closed.
-            None => true,
-        };
+    // The event socket was closed, most likely because Hyprland is exiting.
+    exit_notify.notify_one();
+}
 
-        if should_exit {
+/// A background task that polls hyprland to see if the minimized window
+/// has been closed or restored externally.
+async fn poll_window_state(window: WatchedWindow, exit_notify: Arc<Notify>, hyprland: Hyprland) {
+    let mut interval = interval(Duration::from_millis(window.poll_interval));
+    loop {
+        interval.tick().await;
+
+        // First, check if the window was closed or restored normally.
+        if has_left_minimized_state(&window, &hyprland).await {
             exit_notify.notify_one();
             return;
         }
 
         // If the feature is enabled, check if the window has been focused.
-        if auto_unminimize_on_focus {
-            if let Ok(active_window) = hyprland.exec::<WindowInfo>("activewindow") {
-                if active_window.address == window_address {
-                    // The minimized window is now active. Signal the main process
-                    // to restore it and exit.
-                    exit_notify.notify_one();
-                    return;
-                }
-            }
+        if window.auto_unminimize_on_focus
+            && let Ok(active_window) = hyprland.exec::<WindowInfo>("activewindow").await
+            && active_window.address == window.address
+        {
+            // The minimized window is now active. Signal the main process
+            // to restore it and exit.
+            exit_notify.notify_one();
+            return;
         }
     }
 }
 
+/// Checks whether the window was closed or moved out of the minimized workspace,
+/// whether restored or sent to another special workspace.
+async fn has_left_minimized_state(window: &WatchedWindow, hyprland: &Hyprland) -> bool {
+    let clients = match hyprland.exec::<Vec<WindowInfo>>("clients").await {
+        Ok(clients) => clients,
+        // A hung compositor says nothing about the window; keep it minimized.
+        Err(e) if is_unreachable(&e) => return false,
+        Err(_) => return true,
+    };
+
+    match clients.iter().find(|c| c.address == window.address) {
+        // Window is found, exit if it's no longer in the minimized workspace.
+        Some(client) => client.workspace.name != window.minimized_workspace,
+        // Window is not found, exit because it has been closed.
+        None => true,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::hyprland::{self, Workspace};
+    use crate::hyprland;
     use std::os::unix::process::ExitStatusExt;
     use std::process::{ExitStatus, Output};
     use std::sync::{Arc, Mutex};
     use tempfile::NamedTempFile;
+    use tokio::io::AsyncWriteExt;
 
     // --- Mocking Setup ---
     #[derive(Default, Clone)]
     struct MockHyprctlExecutor {
         dispatched_commands: Arc<Mutex<Vec<String>>>,
+        dispatched_batches: Arc<Mutex<Vec<Vec<String>>>>,
+        json_response: Arc<Mutex<String>>,
     }
+    impl MockHyprctlExecutor {
+        fn with_json_response(json: &str) -> Self {
+            let executor = Self::default();
+            executor.json_response.lock().unwrap().push_str(json);
+            executor
+        }
+    }
+    #[async_trait]
     impl hyprland::HyprctlExecutor for MockHyprctlExecutor {
-        fn execute_json(&self, _command: &str) -> Result<Output> {
+        async fn execute_json(&self, _command: &str) -> Result<Output> {
+            let response = self.json_response.lock().unwrap();
             Ok(Output {
                 status: ExitStatus::from_raw(0),
-                stdout: b"[]".to_vec(),
+                stdout: if response.is_empty() {
+                    b"[]".to_vec()
+                } else {
+                    response.as_bytes().to_vec()
+                },
                 stderr: vec![],
             })
         }
-        fn execute_dispatch(&self, command: &str) -> Result<Output> {
+        async fn execute_dispatch(&self, command: &str) -> Result<Output> {
             self.dispatched_commands
                 .lock()
                 .unwrap()
@@ -338,6 +616,17 @@ mod tests {
                 stderr: vec![],
             })
         }
+        async fn execute_batch(&self, commands: &[String]) -> Result<Output> {
+            self.dispatched_batches
+                .lock()
+                .unwrap()
+                .push(commands.to_vec());
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: vec![],
+                stderr: vec![],
+            })
+        }
     }
 
     struct MockDbus;
@@ -367,7 +656,11 @@ mod tests {
             address: "0xMINIMIZE_TEST".to_string(),
             class: "TestApp".to_string(),
             title: "Test Window".to_string(),
-            workspace: Workspace { id: 1 },
+            workspace: Workspace {
+                id: 1,
+                ..Default::default()
+            },
+            ..Default::default()
         };
 
         let mock_executor = Arc::new(MockHyprctlExecutor::default());
@@ -386,12 +679,19 @@ mod tests {
         );
 
         let dispatched = mock_executor.dispatched_commands.lock().unwrap();
-        assert_eq!(dispatched.len(), 3, "Expected exactly 3 dispatch calls");
         assert_eq!(
-            dispatched[0],
-            "movetoworkspacesilent special:minimized,address:0xMINIMIZE_TEST"
+            *dispatched,
+            ["movetoworkspacesilent special:minimized,address:0xMINIMIZE_TEST"]
+        );
+        // The restore moves and focuses the window in a single batch.
+        let batches = mock_executor.dispatched_batches.lock().unwrap();
+        assert_eq!(
+            *batches,
+            [[
+                "movetoworkspace 1,address:0xMINIMIZE_TEST",
+                "focuswindow address:0xMINIMIZE_TEST"
+            ]]
         );
-        assert_eq!(dispatched[1], "movetoworkspace 1,address:0xMINIMIZE_TEST");
 
         assert!(
             stack.pop()?.is_none(),
@@ -400,4 +700,232 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_pinned_window_is_unpinned_and_pinned_back() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let stack = Stack::new(temp_file.path());
+
+        let test_window = WindowInfo {
+            address: "0xPINNED".to_string(),
+            class: "mpv".to_string(),
+            workspace: Workspace {
+                id: 1,
+                ..Default::default()
+            },
+            pinned: true,
+            ..Default::default()
+        };
+
+        let mock_executor = Arc::new(MockHyprctlExecutor::default());
+        let hyprland = Hyprland::new(mock_executor.clone());
+        let minimizer = Minimizer::new(Config::default(), &stack, test_window, hyprland, &MockDbus);
+        assert!(minimizer.minimize().await.is_err());
+
+        let batches = mock_executor.dispatched_batches.lock().unwrap();
+        assert_eq!(
+            *batches,
+            [
+                vec![
+                    "pin address:0xPINNED",
+                    "movetoworkspacesilent special:minimized,address:0xPINNED"
+                ],
+                vec![
+                    "movetoworkspace 1,address:0xPINNED",
+                    "focuswindow address:0xPINNED",
+                    "pin address:0xPINNED"
+                ]
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_whole_group_is_minimized_and_restored_together() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let stack = Stack::new(temp_file.path());
+
+        let test_window = WindowInfo {
+            address: "0xLEAD".to_string(),
+            workspace: Workspace {
+                id: 1,
+                name: "1".to_string(),
+                ..Default::default()
+            },
+            grouped: vec!["0xLEAD".to_string(), "0xMEMBER".to_string()],
+            ..Default::default()
+        };
+        let config = Config {
+            minimize_whole_group: Some(true),
+            ..Config::default()
+        };
+
+        // The window is back on its workspace, the rest of the group still minimized.
+        let mock_executor = Arc::new(MockHyprctlExecutor::with_json_response(
+            r#"[
+                {"address": "0xLEAD", "workspace": {"id": 1, "name": "1"}, "title": "", "class": ""},
+                {"address": "0xMEMBER", "workspace": {"id": -98, "name": "special:minimized"}, "title": "", "class": ""}
+            ]"#,
+        ));
+        let hyprland = Hyprland::new(mock_executor.clone());
+        let minimizer = Minimizer::new(config, &stack, test_window, hyprland, &MockDbus);
+        assert!(minimizer.minimize().await.is_err());
+
+        let batches = mock_executor.dispatched_batches.lock().unwrap();
+        assert_eq!(
+            *batches,
+            [
+                [
+                    "movetoworkspacesilent special:minimized,address:0xLEAD",
+                    "movetoworkspacesilent special:minimized,address:0xMEMBER"
+                ],
+                [
+                    "movetoworkspace 1,address:0xLEAD",
+                    "focuswindow address:0xLEAD"
+                ]
+            ]
+        );
+        let dispatched = mock_executor.dispatched_commands.lock().unwrap();
+        assert_eq!(
+            *dispatched,
+            [
+                "movetoworkspacesilent 1,address:0xMEMBER",
+                "focuswindow address:0xLEAD"
+            ]
+        );
+        // A single entry stands for the whole group.
+        assert!(stack.pop()?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_excluded_classes_are_restored_without_state() {
+        let window = WindowInfo {
+            class: "steam".to_string(),
+            at: [10, 20],
+            size: [300, 400],
+            floating: true,
+            fullscreen: 1,
+            pinned: true,
+            ..Default::default()
+        };
+        let config = Config {
+            preserve_state_exclude: Some(vec!["steam".to_string()]),
+            ..Config::default()
+        };
+
+        // The pin is only taken away to hide the window, so it always comes back.
+        let entry = StackEntry::from_window(&restorable(&config, &window));
+        assert!(!entry.floating && entry.pinned);
+        assert_eq!((entry.size, entry.fullscreen), ([0, 0], 0));
+
+        let entry = StackEntry::from_window(&restorable(&Config::default(), &window));
+        assert!(entry.floating && entry.pinned);
+        assert_eq!(entry.size, [300, 400]);
+    }
+
+    // --- Event watcher ---
+
+    const MINIMIZED_CLIENTS: &str = r#"[{"address": "0xWATCHED", "workspace": {"id": -98, "name": "special:minimized"}, "title": "Test", "class": "Test"}]"#;
+
+    fn watched_window(auto_unminimize_on_focus: bool) -> WatchedWindow {
+        WatchedWindow {
+            address: "0xWATCHED".to_string(),
+            minimized_workspace: "special:minimized".to_string(),
+            poll_interval: 2000,
+            auto_unminimize_on_focus,
+        }
+    }
+
+    /// Runs the event watcher on an in-memory stream, returning the writing end
+    /// and the notifier the watcher signals.
+    fn spawn_event_watcher(window: WatchedWindow) -> (tokio::io::DuplexStream, Arc<Notify>) {
+        let (client, server) = tokio::io::duplex(1024);
+        let exit_notify = Arc::new(Notify::new());
+        let hyprland = Hyprland::new(Arc::new(MockHyprctlExecutor::with_json_response(
+            MINIMIZED_CLIENTS,
+        )));
+        tokio::spawn(watch_window_events(
+            BufReader::new(server),
+            window,
+            Arc::clone(&exit_notify),
+            hyprland,
+        ));
+        (client, exit_notify)
+    }
+
+    async fn is_notified(exit_notify: &Notify) -> bool {
+        tokio::time::timeout(Duration::from_millis(50), exit_notify.notified())
+            .await
+            .is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_event_watcher_exits_when_window_closes() {
+        let (mut events, exit_notify) = spawn_event_watcher(watched_window(false));
+
+        events.write_all(b"closewindow>>OTHER\n").await.unwrap();
+        assert!(!is_notified(&exit_notify).await);
+
+        events.write_all(b"closewindow>>WATCHED\n").await.unwrap();
+        assert!(is_notified(&exit_notify).await);
+    }
+
+    #[tokio::test]
+    async fn test_event_watcher_exits_when_window_leaves_minimized_workspace() {
+        let (mut events, exit_notify) = spawn_event_watcher(watched_window(false));
+
+        events
+            .write_all(b"movewindowv2>>WATCHED,-98,special:minimized\n")
+            .await
+            .unwrap();
+        assert!(!is_notified(&exit_notify).await);
+
+        events
+            .write_all(b"movewindowv2>>WATCHED,2,2\n")
+            .await
+            .unwrap();
+        assert!(is_notified(&exit_notify).await);
+    }
+
+    #[tokio::test]
+    async fn test_event_watcher_focus_requires_auto_unminimize() {
+        let (mut events, exit_notify) = spawn_event_watcher(watched_window(false));
+        events
+            .write_all(b"activewindowv2>>WATCHED\n")
+            .await
+            .unwrap();
+        assert!(!is_notified(&exit_notify).await);
+
+        let (mut events, exit_notify) = spawn_event_watcher(watched_window(true));
+        events
+            .write_all(b"activewindowv2>>WATCHED\n")
+            .await
+            .unwrap();
+        assert!(is_notified(&exit_notify).await);
+    }
+
+    #[tokio::test]
+    async fn test_other_special_workspaces_are_not_minimized() {
+        let minimized = Hyprland::new(Arc::new(MockHyprctlExecutor::with_json_response(
+            MINIMIZED_CLIENTS,
+        )));
+        assert!(!has_left_minimized_state(&watched_window(false), &minimized).await);
+
+        // Sent to a scratchpad: no longer minimized, although its id is negative too.
+        let scratchpad = Hyprland::new(Arc::new(MockHyprctlExecutor::with_json_response(
+            r#"[{"address": "0xWATCHED", "workspace": {"id": -97, "name": "special:term"}, "title": "Test", "class": "Test"}]"#,
+        )));
+        assert!(has_left_minimized_state(&watched_window(false), &scratchpad).await);
+    }
+
+    #[tokio::test]
+    async fn test_event_watcher_exits_when_socket_closes() {
+        let (events, exit_notify) = spawn_event_watcher(watched_window(false));
+
+        drop(events);
+        assert!(is_notified(&exit_notify).await);
+    }
 }
@@ -0,0 +1,312 @@
+//! Renders tray icons as the pixmaps of the `IconPixmap` property, for hosts that
+//! cannot look an icon up by its name.
+use resvg::tiny_skia::{
+    self, Color, FillRule, FilterQuality, Paint, PathBuilder, PixmapPaint, Rect, Transform,
+};
+use resvg::usvg;
+use std::fs;
+use std::path::Path;
+
+/// An icon as `IconPixmap` carries it: its width, its height and its ARGB32 pixels in
+/// network byte order.
+pub type Pixmap = (i32, i32, Vec<u8>);
+
+/// The sizes icons are served at, hosts picking the one closest to what they show.
+pub const SIZES: [u32; 3] = [16, 32, 64];
+
+// The background colors of the letter tiles.
+const TILE_COLORS: [(u8, u8, u8); 8] = [
+    (0xc0, 0x39, 0x2b),
+    (0xd3, 0x54, 0x00),
+    (0xb7, 0x95, 0x0b),
+    (0x27, 0xae, 0x60),
+    (0x16, 0xa0, 0x85),
+    (0x29, 0x80, 0xb9),
+    (0x8e, 0x44, 0xad),
+    (0x7f, 0x8c, 0x8d),
+];
+
+// A 5x7 bitmap font for the letter tiles, one row per byte, most significant bit left.
+#[rustfmt::skip]
+const GLYPHS: [(char, [u8; 7]); 37] = [
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('D', [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('Y', [0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    ('?', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100]),
+];
+
+/// Renders the PNG or SVG icon at `path` at each of `SIZES`, or returns `None` when it
+/// cannot be read.
+pub fn load(path: &Path) -> Option<Vec<Pixmap>> {
+    let data = fs::read(path).ok()?;
+    let is_svg = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("svg"));
+    if is_svg {
+        let tree = usvg::Tree::from_data(&data, &usvg::Options::default()).ok()?;
+        SIZES
+            .iter()
+            .map(|&size| render_svg(&tree, size).map(|pixmap| to_argb(&pixmap)))
+            .collect()
+    } else {
+        let image = tiny_skia::Pixmap::decode_png(&data).ok()?;
+        SIZES
+            .iter()
+            .map(|&size| scale(&image, size).map(|pixmap| to_argb(&pixmap)))
+            .collect()
+    }
+}
+
+/// A colored disc with the first letter or digit of `label`, for icons found nowhere.
+/// The color only depends on the label, so a class always gets the same tile.
+pub fn letter_tile(label: &str) -> Vec<Pixmap> {
+    let letter = label
+        .chars()
+        .find(char::is_ascii_alphanumeric)
+        .map_or('?', |c| c.to_ascii_uppercase());
+    let (_, glyph) = GLYPHS
+        .iter()
+        .find(|(c, _)| *c == letter)
+        .copied()
+        .unwrap_or(GLYPHS[GLYPHS.len() - 1]);
+    // FNV-1a, which is stable across runs unlike the standard library hasher.
+    let hash = label.bytes().fold(0x811c9dc5u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    });
+    let (r, g, b) = TILE_COLORS[hash as usize % TILE_COLORS.len()];
+
+    SIZES
+        .iter()
+        .filter_map(|&size| {
+            let mut pixmap = tiny_skia::Pixmap::new(size, size)?;
+            let half = size as f32 / 2.0;
+            let disc = PathBuilder::from_circle(half, half, half)?;
+            let mut paint = Paint::default();
+            paint.set_color_rgba8(r, g, b, 255);
+            paint.anti_alias = true;
+            pixmap.fill_path(
+                &disc,
+                &paint,
+                FillRule::Winding,
+                Transform::identity(),
+                None,
+            );
+
+            // The glyph, centered, with each of its dots drawn as a square.
+            paint.set_color(Color::WHITE);
+            paint.anti_alias = false;
+            let dot = (size / 10).max(1);
+            let left = (size - 5 * dot) / 2;
+            let top = (size - 7 * dot) / 2;
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..5 {
+                    if bits & (0b10000 >> column) == 0 {
+                        continue;
+                    }
+                    let x = left + column * dot;
+                    let y = top + row as u32 * dot;
+                    let rect = Rect::from_xywh(x as f32, y as f32, dot as f32, dot as f32)?;
+                    pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+                }
+            }
+            Some(to_argb(&pixmap))
+        })
+        .collect()
+}
+
+/// Renders `tree` in a square of `size` pixels, keeping its aspect ratio.
+fn render_svg(tree: &usvg::Tree, size: u32) -> Option<tiny_skia::Pixmap> {
+    let mut pixmap = tiny_skia::Pixmap::new(size, size)?;
+    let transform = fit(tree.size().width(), tree.size().height(), size)?;
+    resvg::render(tree, transform, &mut pixmap.as_mut());
+    Some(pixmap)
+}
+
+/// Scales `image` into a square of `size` pixels, keeping its aspect ratio.
+fn scale(image: &tiny_skia::Pixmap, size: u32) -> Option<tiny_skia::Pixmap> {
+    let mut pixmap = tiny_skia::Pixmap::new(size, size)?;
+    let transform = fit(image.width() as f32, image.height() as f32, size)?;
+    let paint = PixmapPaint {
+        quality: FilterQuality::Bicubic,
+        ..Default::default()
+    };
+    pixmap.draw_pixmap(0, 0, image.as_ref(), &paint, transform, None);
+    Some(pixmap)
+}
+
+/// The transform that centers a `width` x `height` image in a square of `size` pixels,
+/// scaled to fill it along its longest side.
+fn fit(width: f32, height: f32, size: u32) -> Option<Transform> {
+    if width <= 0.0 || height <= 0.0 {
+        return None;
+    }
+    let size = size as f32;
+    let ratio = size / width.max(height);
+    Some(
+        Transform::from_scale(ratio, ratio)
+            .post_translate((size - width * ratio) / 2.0, (size - height * ratio) / 2.0),
+    )
+}
+
+/// Converts the premultiplied RGBA pixels of tiny-skia to straight ARGB32, most
+/// significant byte first.
+fn to_argb(pixmap: &tiny_skia::Pixmap) -> Pixmap {
+    let data = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.alpha(), color.red(), color.green(), color.blue()]
+        })
+        .collect();
+    (pixmap.width() as i32, pixmap.height() as i32, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    // The ARGB bytes of the pixel at `x`, `y`.
+    fn pixel(pixmap: &Pixmap, x: usize, y: usize) -> [u8; 4] {
+        let offset = (y * pixmap.0 as usize + x) * 4;
+        pixmap.2[offset..offset + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn test_to_argb_orders_bytes_alpha_first_and_demultiplies() {
+        let mut image = tiny_skia::Pixmap::new(2, 1).unwrap();
+        image.fill_rect(
+            Rect::from_xywh(0.0, 0.0, 1.0, 1.0).unwrap(),
+            &Paint {
+                shader: tiny_skia::Shader::SolidColor(Color::from_rgba8(0x11, 0x22, 0x33, 0xff)),
+                ..Default::default()
+            },
+            Transform::identity(),
+            None,
+        );
+        // Half transparent red, stored by tiny-skia as (0x80, 0, 0, 0x80).
+        image.pixels_mut()[1] = tiny_skia::ColorU8::from_rgba(0xff, 0, 0, 0x80).premultiply();
+
+        let (width, height, data) = to_argb(&image);
+
+        assert_eq!((width, height), (2, 1));
+        assert_eq!(data, vec![0xff, 0x11, 0x22, 0x33, 0x80, 0xff, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_load_png_fixture_at_every_size() {
+        // An 8x4 fixture, opaque blue on the left half and transparent on the right.
+        let mut fixture = tiny_skia::Pixmap::new(8, 4).unwrap();
+        fixture.fill_rect(
+            Rect::from_xywh(0.0, 0.0, 4.0, 4.0).unwrap(),
+            &Paint {
+                shader: tiny_skia::Shader::SolidColor(Color::from_rgba8(0, 0, 0xff, 0xff)),
+                ..Default::default()
+            },
+            Transform::identity(),
+            None,
+        );
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("fixture.png");
+        fixture.save_png(&path).unwrap();
+
+        let pixmaps = load(&path).unwrap();
+
+        let sizes: Vec<(i32, i32)> = pixmaps.iter().map(|(w, h, _)| (*w, *h)).collect();
+        assert_eq!(sizes, vec![(16, 16), (32, 32), (64, 64)]);
+        for pixmap in &pixmaps {
+            let size = pixmap.0 as usize;
+            assert_eq!(pixmap.2.len(), size * size * 4);
+            // Centered vertically: the bands above and below the image stay empty.
+            assert_eq!(pixel(pixmap, size / 4, size / 2), [0xff, 0, 0, 0xff]);
+            assert_eq!(pixel(pixmap, size * 3 / 4, size / 2)[0], 0);
+            assert_eq!(pixel(pixmap, size / 4, 0)[0], 0);
+        }
+    }
+
+    #[test]
+    fn test_load_svg_fixture_at_every_size() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("fixture.svg");
+        fs::write(
+            &path,
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10">
+                <rect x="0" y="0" width="5" height="10" fill="#00ff00"/>
+            </svg>"##,
+        )
+        .unwrap();
+
+        let pixmaps = load(&path).unwrap();
+
+        assert_eq!(pixmaps.len(), SIZES.len());
+        for (pixmap, size) in pixmaps.iter().zip(SIZES) {
+            let size = size as usize;
+            assert_eq!((pixmap.0, pixmap.1), (size as i32, size as i32));
+            assert_eq!(pixel(pixmap, size / 4, size / 2), [0xff, 0, 0xff, 0]);
+            assert_eq!(pixel(pixmap, size * 3 / 4, size / 2)[0], 0);
+        }
+    }
+
+    #[test]
+    fn test_load_rejects_unreadable_icons() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("broken.png");
+        fs::write(&path, b"not a png").unwrap();
+
+        assert!(load(&path).is_none());
+        assert!(load(&dir.path().join("missing.svg")).is_none());
+    }
+
+    #[test]
+    fn test_letter_tile() {
+        let tiles = letter_tile("steam_app_570");
+
+        assert_eq!(tiles.len(), SIZES.len());
+        assert_eq!(tiles, letter_tile("steam_app_570"));
+        for tile in &tiles {
+            let size = tile.0 as usize;
+            // Transparent corners around an opaque disc.
+            assert_eq!(pixel(tile, 0, 0)[0], 0);
+            assert_eq!(pixel(tile, size / 2, 1)[0], 0xff);
+            // The top bar of the "S", in white.
+            let dot = (size / 10).max(1);
+            let top = (size - 7 * dot) / 2;
+            assert_eq!(pixel(tile, size / 2, top), [0xff, 0xff, 0xff, 0xff]);
+        }
+        assert_ne!(letter_tile("firefox"), letter_tile("kitty"));
+    }
+}
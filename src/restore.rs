@@ -1,8 +1,8 @@
 //! Contains the logic for restoring the last minimized window.
 use crate::cli::RestoreTarget;
 use crate::config::Config;
-use crate::hyprland::{Hyprland, WindowInfo, Workspace};
-use crate::stack::Stack;
+use crate::hyprland::{CursorPosition, Hyprland, Monitor, WindowInfo, Workspace};
+use crate::stack::{Stack, StackEntry, StackError};
 
 use anyhow::{Context, Result};
 
@@ -12,46 +12,265 @@ pub async fn restore_last_minimized(
     stack: &Stack,
     hyprland: &Hyprland,
 ) -> Result<()> {
-    let Some(address) = stack.pop()? else {
-        println!("No minimized windows in the stack to restore.");
-        return Ok(());
+    let Some(entry) = stack.pop()? else {
+        return Err(StackError::Empty.into());
     };
+    let address = &entry.address;
 
     println!("Restoring last minimized window: {address}");
 
     let clients: Vec<WindowInfo> = hyprland
         .exec("clients")
+        .await
         .context("Failed to get client list to verify window existence.")?;
 
-    let is_minimized = clients
-        .iter()
-        .any(|c| c.address == address && c.workspace.id < 0);
+    let minimized_workspace = config.workspace.clone().unwrap();
+    let window = clients.iter().find(|c| &c.address == address);
+
+    if let Some(window) = window
+        && !entry.identifies(window)
+    {
+        println!(
+            "Window {address} was closed and its address now belongs to '{}' ({}). Dropping it.",
+            window.title, window.class
+        );
+        return Ok(());
+    }
+    let is_minimized = window.is_some_and(|c| c.workspace.name == minimized_workspace);
 
     if !is_minimized {
         println!("Window {address} no longer exists or is not minimized. Stack is clean.");
         return Ok(());
     }
 
-    if config.restore_to.unwrap() == RestoreTarget::Active {
-        let active_workspace: Workspace = hyprland
-            .exec("activeworkspace")
-            .context("Failed to get active workspace for restoration.")?;
+    restore_to_outside(
+        hyprland,
+        &entry,
+        config.restore_to.unwrap(),
+        &minimized_workspace,
+    )
+    .await
+}
+
+/// Moves a minimized window to the target workspace and focuses it. `entry` is the
+/// state recorded when the window was minimized, which holds its original workspace.
+pub async fn restore_to(
+    hyprland: &Hyprland,
+    entry: &StackEntry,
+    target: RestoreTarget,
+) -> Result<()> {
+    let workspace = target_workspace(hyprland, entry, target).await?;
+    restore_into_reporting(hyprland, entry, &workspace).await
+}
 
-        hyprland.dispatch(&format!(
-            "movetoworkspace {},address:{}",
-            active_workspace.id, address
-        ))?;
-        println!("Window restored to workspace {}.", active_workspace.id);
+/// Like `restore_to`, but never into `minimized_workspace`, where the window would
+/// stay hidden, e.g. when it was minimized from there: it goes to the active
+/// workspace instead.
+pub async fn restore_to_outside(
+    hyprland: &Hyprland,
+    entry: &StackEntry,
+    target: RestoreTarget,
+    minimized_workspace: &str,
+) -> Result<()> {
+    let mut workspace = target_workspace(hyprland, entry, target).await?;
+    if workspace.name == minimized_workspace {
+        workspace = active_workspace(hyprland).await?;
     }
-    hyprland.dispatch(&format!("focuswindow address:{address}"))?;
+    restore_into_reporting(hyprland, entry, &workspace).await
+}
 
+async fn restore_into_reporting(
+    hyprland: &Hyprland,
+    entry: &StackEntry,
+    workspace: &Workspace,
+) -> Result<()> {
+    restore_into(hyprland, entry, Some(workspace)).await?;
+    println!(
+        "Window restored to workspace {}.",
+        workspace.dispatch_target()
+    );
     Ok(())
 }
 
+/// Moves a minimized window to `workspace` when it is known, gives it back the state
+/// recorded in `entry` and puts it back into its group.
+pub async fn restore_into(
+    hyprland: &Hyprland,
+    entry: &StackEntry,
+    workspace: Option<&Workspace>,
+) -> Result<()> {
+    hyprland
+        .dispatch_batch(&restore_commands(entry, workspace))
+        .await?;
+    rejoin_group(hyprland, &entry.address, &entry.group).await
+}
+
+/// Puts a restored window back into `group`, the group it was minimized from, next
+/// to a member on its workspace. The group is formed again around that member when
+/// it has since been dissolved. Does nothing when no member is left there.
+pub async fn rejoin_group(hyprland: &Hyprland, address: &str, group: &[String]) -> Result<()> {
+    if group.iter().all(|member| member == address) {
+        return Ok(());
+    }
+    let clients: Vec<WindowInfo> = hyprland
+        .exec("clients")
+        .await
+        .context("Failed to get client list to restore the window's group.")?;
+    let Some(window) = clients.iter().find(|c| c.address == address) else {
+        return Ok(());
+    };
+    if window
+        .grouped
+        .iter()
+        .any(|m| m != address && group.contains(m))
+    {
+        return Ok(());
+    }
+    // Prefer a member that is still grouped, to join its group rather than start one.
+    let Some(member) = clients
+        .iter()
+        .filter(|c| c.address != address && group.contains(&c.address))
+        .filter(|c| c.workspace.id == window.workspace.id)
+        .max_by_key(|c| !c.grouped.is_empty())
+    else {
+        return Ok(());
+    };
+
+    let mut commands = Vec::new();
+    if member.grouped.is_empty() {
+        commands.push(format!("focuswindow address:{}", member.address));
+        commands.push("togglegroup".to_string());
+    }
+    // `moveintogroup` moves the focused window into the group in a direction.
+    commands.push(format!("focuswindow address:{address}"));
+    commands.push(format!("moveintogroup {}", direction(window, member)));
+    hyprland.dispatch_batch(&commands).await
+}
+
+/// The direction, as Hyprland names it, from the center of `from` to that of `to`.
+fn direction(from: &WindowInfo, to: &WindowInfo) -> &'static str {
+    let center = |w: &WindowInfo| (w.at[0] * 2 + w.size[0], w.at[1] * 2 + w.size[1]);
+    let ((from_x, from_y), (to_x, to_y)) = (center(from), center(to));
+    let (dx, dy) = (to_x - from_x, to_y - from_y);
+    if dx.abs() >= dy.abs() {
+        if dx < 0 { "l" } else { "r" }
+    } else if dy < 0 {
+        "u"
+    } else {
+        "d"
+    }
+}
+
+/// The dispatches that bring a minimized window back: moved to `workspace` when it
+/// is known, focused, then given back the state recorded in `entry`.
+pub fn restore_commands(entry: &StackEntry, workspace: Option<&Workspace>) -> Vec<String> {
+    let address = &entry.address;
+    let mut commands = Vec::new();
+    if let Some(workspace) = workspace {
+        commands.push(format!(
+            "movetoworkspace {},address:{address}",
+            workspace.dispatch_target()
+        ));
+    }
+    commands.push(format!("focuswindow address:{address}"));
+
+    if entry.floating && entry.size != [0, 0] {
+        commands.push(format!("setfloating address:{address}"));
+        commands.push(format!(
+            "resizewindowpixel exact {} {},address:{address}",
+            entry.size[0], entry.size[1]
+        ));
+        // Positions are global, so they only hold on the monitor the window left.
+        if workspace.is_none_or(|workspace| workspace.monitor_id == Some(entry.monitor)) {
+            commands.push(format!(
+                "movewindowpixel exact {} {},address:{address}",
+                entry.at[0], entry.at[1]
+            ));
+        }
+    }
+    // Moving the window to the minimized workspace dropped its fullscreen mode, so
+    // `fullscreen` toggles it back on: 1 maximizes, 0 goes fullscreen. It applies to
+    // the focused window, hence after `focuswindow`. Unlike `fullscreenstate`, it is
+    // understood by Hyprland versions before 0.42.
+    if entry.fullscreen != 0 {
+        let mode = if entry.fullscreen == 1 { 1 } else { 0 };
+        commands.push(format!("fullscreen {mode}"));
+    }
+    // Pinned windows are unpinned when minimized, as they would show everywhere.
+    if entry.pinned {
+        commands.push(format!("pin address:{address}"));
+    }
+    commands
+}
+
+/// Resolves the workspace a restore target points at.
+pub async fn target_workspace(
+    hyprland: &Hyprland,
+    entry: &StackEntry,
+    target: RestoreTarget,
+) -> Result<Workspace> {
+    match target {
+        // Entries migrated from the old stack format don't know their workspace, so
+        // they go to the active one rather than staying minimized.
+        RestoreTarget::Active => active_workspace(hyprland).await,
+        RestoreTarget::Original if entry.workspace_id == 0 => active_workspace(hyprland).await,
+        RestoreTarget::Original => Ok(Workspace {
+            id: entry.workspace_id,
+            name: entry.workspace_name.clone(),
+            monitor_id: Some(entry.monitor),
+            ..Default::default()
+        }),
+        RestoreTarget::FocusedMonitor
+        | RestoreTarget::CursorMonitor
+        | RestoreTarget::OriginalMonitor => monitor_workspace(hyprland, entry, target).await,
+    }
+}
+
+async fn active_workspace(hyprland: &Hyprland) -> Result<Workspace> {
+    hyprland
+        .exec("activeworkspace")
+        .await
+        .context("Failed to get active workspace for restoration.")
+}
+
+/// The workspace shown on the monitor a target points at. A monitor that has been
+/// unplugged, or a cursor outside of every monitor, falls back to the focused one.
+async fn monitor_workspace(
+    hyprland: &Hyprland,
+    entry: &StackEntry,
+    target: RestoreTarget,
+) -> Result<Workspace> {
+    let monitors: Vec<Monitor> = hyprland
+        .exec("monitors")
+        .await
+        .context("Failed to get monitors for restoration.")?;
+    let monitors: Vec<&Monitor> = monitors.iter().filter(|m| !m.disabled).collect();
+
+    let monitor = match target {
+        RestoreTarget::CursorMonitor => {
+            let cursor: CursorPosition = hyprland
+                .exec("cursorpos")
+                .await
+                .context("Failed to get cursor position for restoration.")?;
+            monitors.iter().find(|m| m.contains(cursor.x, cursor.y))
+        }
+        RestoreTarget::OriginalMonitor => monitors.iter().find(|m| m.id == entry.monitor),
+        _ => None,
+    };
+    match monitor.or_else(|| monitors.iter().find(|m| m.focused)) {
+        Some(monitor) => Ok(Workspace {
+            monitor_id: Some(monitor.id),
+            ..monitor.active_workspace.clone()
+        }),
+        None => active_workspace(hyprland).await,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::hyprland;
+    use async_trait::async_trait;
     use std::os::unix::process::ExitStatusExt;
     use std::process::{ExitStatus, Output};
     use std::sync::{Arc, Mutex};
@@ -62,6 +281,7 @@ mod tests {
     #[derive(Default, Clone)]
     struct MockExecutor {
         dispatched_commands: Arc<Mutex<Vec<String>>>,
+        dispatched_batches: Arc<Mutex<Vec<Vec<String>>>>,
         json_responses: Arc<Mutex<Vec<String>>>,
     }
     impl MockExecutor {
@@ -71,9 +291,13 @@ mod tests {
         fn dispatched_commands(&self) -> Vec<String> {
             self.dispatched_commands.lock().unwrap().clone()
         }
+        fn dispatched_batches(&self) -> Vec<Vec<String>> {
+            self.dispatched_batches.lock().unwrap().clone()
+        }
     }
+    #[async_trait]
     impl hyprland::HyprctlExecutor for MockExecutor {
-        fn execute_json(&self, _command: &str) -> Result<Output> {
+        async fn execute_json(&self, _command: &str) -> Result<Output> {
             let response = self
                 .json_responses
                 .lock()
@@ -86,7 +310,7 @@ mod tests {
                 stderr: vec![],
             })
         }
-        fn execute_dispatch(&self, command: &str) -> Result<Output> {
+        async fn execute_dispatch(&self, command: &str) -> Result<Output> {
             self.dispatched_commands
                 .lock()
                 .unwrap()
@@ -97,6 +321,17 @@ mod tests {
                 stderr: vec![],
             })
         }
+        async fn execute_batch(&self, commands: &[String]) -> Result<Output> {
+            self.dispatched_batches
+                .lock()
+                .unwrap()
+                .push(commands.to_vec());
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: vec![],
+                stderr: vec![],
+            })
+        }
     }
 
     #[tokio::test]
@@ -112,15 +347,18 @@ mod tests {
         // 1. `hyprctl activeworkspace` will be called second.
         mock_executor.add_json_response(r#"{"id": 3}"#);
         // 2. `hyprctl clients` will be called first.
-        mock_executor.add_json_response(r#"[{"address": "0xRESTORE_TEST", "workspace": {"id": -99}, "title": "Test", "class": "Test"}]"#);
+        mock_executor.add_json_response(r#"[{"address": "0xRESTORE_TEST", "workspace": {"id": -99, "name": "special:minimized"}, "title": "Test", "class": "Test"}]"#);
 
         // Directly .await the function with the mock-powered hyprland instance.
         restore_last_minimized(Config::default(), &stack, &hyprland).await?;
 
-        let dispatched = mock_executor.dispatched_commands();
-        assert_eq!(dispatched.len(), 2);
-        assert_eq!(dispatched[0], "movetoworkspace 3,address:0xRESTORE_TEST");
-        assert_eq!(dispatched[1], "focuswindow address:0xRESTORE_TEST");
+        assert_eq!(
+            mock_executor.dispatched_batches(),
+            [[
+                "movetoworkspace 3,address:0xRESTORE_TEST",
+                "focuswindow address:0xRESTORE_TEST"
+            ]]
+        );
 
         // The stack should be empty after a successful restore.
         assert!(stack.pop()?.is_none());
@@ -137,19 +375,44 @@ mod tests {
         let mock_executor = Arc::new(MockExecutor::default());
         let hyprland = Hyprland::new(mock_executor.clone() as Arc<dyn hyprland::HyprctlExecutor>);
 
-        // The window is on workspace 2, not a special workspace.
+        // The window is on workspace 2, not the minimized workspace.
         mock_executor.add_json_response(r#"[{"address": "0xALREADY_OPEN", "workspace": {"id": 2}, "title": "Test", "class": "Test"}]"#);
 
         restore_last_minimized(Config::default(), &stack, &hyprland).await?;
 
         // No commands should be dispatched if the window isn't minimized.
         assert!(mock_executor.dispatched_commands().is_empty());
+        assert!(mock_executor.dispatched_batches().is_empty());
         // The stack should still be empty as the item was popped and consumed.
         assert!(stack.pop()?.is_none());
 
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_restore_drops_window_with_recycled_address() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let stack = Stack::new(temp_file.path());
+        stack.push(StackEntry {
+            pid: 100,
+            initial_class: "firefox".to_string(),
+            ..StackEntry::from("0xREUSED")
+        })?;
+
+        let mock_executor = Arc::new(MockExecutor::default());
+        let hyprland = Hyprland::new(mock_executor.clone() as Arc<dyn hyprland::HyprctlExecutor>);
+        // Another window now has the address, and happens to be minimized too.
+        mock_executor.add_json_response(r#"[{"address": "0xREUSED", "workspace": {"id": -98, "name": "special:minimized"}, "title": "Other", "class": "mpv", "initialClass": "mpv", "pid": 300}]"#);
+
+        restore_last_minimized(Config::default(), &stack, &hyprland).await?;
+
+        assert!(mock_executor.dispatched_commands().is_empty());
+        assert!(mock_executor.dispatched_batches().is_empty());
+        assert!(stack.pop()?.is_none());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_restore_with_empty_stack() -> Result<()> {
         let temp_file = NamedTempFile::new()?;
@@ -158,11 +421,329 @@ mod tests {
         let mock_executor = Arc::new(MockExecutor::default());
         let hyprland = Hyprland::new(mock_executor.clone() as Arc<dyn hyprland::HyprctlExecutor>);
 
-        restore_last_minimized(Config::default(), &stack, &hyprland).await?;
+        let error = restore_last_minimized(Config::default(), &stack, &hyprland)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<StackError>(),
+            Some(StackError::Empty)
+        ));
 
         // No commands should be dispatched if the stack is empty.
         assert!(mock_executor.dispatched_commands().is_empty());
+        assert!(mock_executor.dispatched_batches().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_to_original_workspace_from_entry() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let stack = Stack::new(temp_file.path());
+        stack.push(StackEntry {
+            workspace_id: 4,
+            ..StackEntry::from("0xORIGINAL")
+        })?;
+
+        let mock_executor = Arc::new(MockExecutor::default());
+        let hyprland = Hyprland::new(mock_executor.clone() as Arc<dyn hyprland::HyprctlExecutor>);
+
+        // The window has since been renamed; only the recorded workspace matters.
+        mock_executor.add_json_response(r#"[{"address": "0xORIGINAL", "workspace": {"id": -99, "name": "special:minimized"}, "title": "Renamed", "class": "Test"}]"#);
+
+        let config = Config {
+            restore_to: Some(RestoreTarget::Original),
+            ..Config::default()
+        };
+        restore_last_minimized(config, &stack, &hyprland).await?;
+
+        assert_eq!(
+            mock_executor.dispatched_batches(),
+            [[
+                "movetoworkspace 4,address:0xORIGINAL",
+                "focuswindow address:0xORIGINAL"
+            ]]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_legacy_entry_to_original_uses_the_active_workspace() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let stack = Stack::new(temp_file.path());
+        // Migrated from the old stack format, which only recorded the address.
+        stack.push("0xLEGACY")?;
+
+        let mock_executor = Arc::new(MockExecutor::default());
+        let hyprland = Hyprland::new(mock_executor.clone() as Arc<dyn hyprland::HyprctlExecutor>);
+        mock_executor.add_json_response(r#"{"id": 5}"#);
+        mock_executor.add_json_response(r#"[{"address": "0xLEGACY", "workspace": {"id": -99, "name": "special:minimized"}, "title": "Test", "class": "Test"}]"#);
+
+        let config = Config {
+            restore_to: Some(RestoreTarget::Original),
+            ..Config::default()
+        };
+        restore_last_minimized(config, &stack, &hyprland).await?;
+
+        assert_eq!(
+            mock_executor.dispatched_batches(),
+            [[
+                "movetoworkspace 5,address:0xLEGACY",
+                "focuswindow address:0xLEGACY"
+            ]]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_never_targets_the_minimized_workspace() -> Result<()> {
+        let mock_executor = Arc::new(MockExecutor::default());
+        let hyprland = Hyprland::new(mock_executor.clone() as Arc<dyn hyprland::HyprctlExecutor>);
+        mock_executor.add_json_response(r#"{"id": 2}"#);
+
+        // Minimized from the minimized workspace itself, e.g. after a crash.
+        let entry = StackEntry {
+            workspace_id: -98,
+            workspace_name: "special:minimized".to_string(),
+            ..StackEntry::from("0xSTUCK")
+        };
+        restore_to_outside(
+            &hyprland,
+            &entry,
+            RestoreTarget::Original,
+            "special:minimized",
+        )
+        .await?;
+
+        assert_eq!(
+            mock_executor.dispatched_batches(),
+            [[
+                "movetoworkspace 2,address:0xSTUCK",
+                "focuswindow address:0xSTUCK"
+            ]]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_to_original_named_workspace() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let stack = Stack::new(temp_file.path());
+        // Named workspaces have negative ids, which are reassigned when recreated.
+        stack.push(StackEntry {
+            workspace_id: -1337,
+            workspace_name: "code".to_string(),
+            ..StackEntry::from("0xNAMED")
+        })?;
+
+        let mock_executor = Arc::new(MockExecutor::default());
+        let hyprland = Hyprland::new(mock_executor.clone() as Arc<dyn hyprland::HyprctlExecutor>);
+        mock_executor.add_json_response(r#"[{"address": "0xNAMED", "workspace": {"id": -99, "name": "special:minimized"}, "title": "Editor", "class": "code"}]"#);
+
+        let config = Config {
+            restore_to: Some(RestoreTarget::Original),
+            ..Config::default()
+        };
+        restore_last_minimized(config, &stack, &hyprland).await?;
+
+        assert_eq!(
+            mock_executor.dispatched_batches(),
+            [[
+                "movetoworkspace name:code,address:0xNAMED",
+                "focuswindow address:0xNAMED"
+            ]]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_ignores_window_in_another_special_workspace() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let stack = Stack::new(temp_file.path());
+        stack.push("0xSCRATCHPAD")?;
+
+        let mock_executor = Arc::new(MockExecutor::default());
+        let hyprland = Hyprland::new(mock_executor.clone() as Arc<dyn hyprland::HyprctlExecutor>);
+        mock_executor.add_json_response(r#"[{"address": "0xSCRATCHPAD", "workspace": {"id": -97, "name": "special:term"}, "title": "Term", "class": "kitty"}]"#);
+
+        restore_last_minimized(Config::default(), &stack, &hyprland).await?;
+
+        assert!(mock_executor.dispatched_batches().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_to_original_special_workspace() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let stack = Stack::new(temp_file.path());
+        stack.push(StackEntry {
+            workspace_id: -97,
+            workspace_name: "special:term".to_string(),
+            ..StackEntry::from("0xTERM")
+        })?;
+
+        let mock_executor = Arc::new(MockExecutor::default());
+        let hyprland = Hyprland::new(mock_executor.clone() as Arc<dyn hyprland::HyprctlExecutor>);
+        mock_executor.add_json_response(r#"[{"address": "0xTERM", "workspace": {"id": -98, "name": "special:minimized"}, "title": "Term", "class": "kitty"}]"#);
+
+        let config = Config {
+            restore_to: Some(RestoreTarget::Original),
+            ..Config::default()
+        };
+        restore_last_minimized(config, &stack, &hyprland).await?;
+
+        assert_eq!(
+            mock_executor.dispatched_batches(),
+            [[
+                "movetoworkspace special:term,address:0xTERM",
+                "focuswindow address:0xTERM"
+            ]]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_commands_bring_back_floating_fullscreen_and_pin() {
+        let entry = StackEntry {
+            monitor: 1,
+            floating: true,
+            at: [100, 200],
+            size: [800, 600],
+            fullscreen: 2,
+            pinned: true,
+            ..StackEntry::from("0xSTATE")
+        };
+        let workspace = Workspace {
+            id: 3,
+            name: "3".to_string(),
+            monitor_id: Some(1),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            restore_commands(&entry, Some(&workspace)),
+            [
+                "movetoworkspace 3,address:0xSTATE",
+                "focuswindow address:0xSTATE",
+                "setfloating address:0xSTATE",
+                "resizewindowpixel exact 800 600,address:0xSTATE",
+                "movewindowpixel exact 100 200,address:0xSTATE",
+                "fullscreen 0",
+                "pin address:0xSTATE",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_restore_commands_maximize_maximized_windows() {
+        let entry = StackEntry {
+            fullscreen: 1,
+            ..StackEntry::from("0xMAX")
+        };
+        assert_eq!(
+            restore_commands(&entry, None),
+            ["focuswindow address:0xMAX", "fullscreen 1"]
+        );
+    }
+
+    #[test]
+    fn test_restore_commands_keep_position_on_its_monitor_only() {
+        let entry = StackEntry {
+            monitor: 0,
+            floating: true,
+            at: [100, 200],
+            size: [800, 600],
+            ..StackEntry::from("0xFLOAT")
+        };
+        let workspace = Workspace {
+            id: 4,
+            name: "4".to_string(),
+            monitor_id: Some(1),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            restore_commands(&entry, Some(&workspace)),
+            [
+                "movetoworkspace 4,address:0xFLOAT",
+                "focuswindow address:0xFLOAT",
+                "setfloating address:0xFLOAT",
+                "resizewindowpixel exact 800 600,address:0xFLOAT",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejoin_group_moves_into_surviving_group() -> Result<()> {
+        let mock_executor = Arc::new(MockExecutor::default());
+        let hyprland = Hyprland::new(mock_executor.clone() as Arc<dyn hyprland::HyprctlExecutor>);
+        mock_executor.add_json_response(
+            r#"[
+                {"address": "0xBACK", "workspace": {"id": 2}, "title": "", "class": "", "at": [960, 0], "size": [960, 1080]},
+                {"address": "0xTAB", "workspace": {"id": 2}, "title": "", "class": "", "at": [0, 0], "size": [960, 1080], "grouped": ["0xTAB", "0xOTHER"]}
+            ]"#,
+        );
+
+        let group = [
+            "0xTAB".to_string(),
+            "0xBACK".to_string(),
+            "0xOTHER".to_string(),
+        ];
+        rejoin_group(&hyprland, "0xBACK", &group).await?;
+
+        assert_eq!(
+            mock_executor.dispatched_batches(),
+            [["focuswindow address:0xBACK", "moveintogroup l"]]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rejoin_group_forms_dissolved_group_again() -> Result<()> {
+        let mock_executor = Arc::new(MockExecutor::default());
+        let hyprland = Hyprland::new(mock_executor.clone() as Arc<dyn hyprland::HyprctlExecutor>);
+        mock_executor.add_json_response(
+            r#"[
+                {"address": "0xBACK", "workspace": {"id": 2}, "title": "", "class": "", "at": [0, 540], "size": [1920, 540]},
+                {"address": "0xTAB", "workspace": {"id": 2}, "title": "", "class": "", "at": [0, 0], "size": [1920, 540]},
+                {"address": "0xELSEWHERE", "workspace": {"id": 3}, "title": "", "class": "", "grouped": ["0xELSEWHERE"]}
+            ]"#,
+        );
+
+        let group = [
+            "0xTAB".to_string(),
+            "0xBACK".to_string(),
+            "0xELSEWHERE".to_string(),
+        ];
+        rejoin_group(&hyprland, "0xBACK", &group).await?;
+
+        assert_eq!(
+            mock_executor.dispatched_batches(),
+            [[
+                "focuswindow address:0xTAB",
+                "togglegroup",
+                "focuswindow address:0xBACK",
+                "moveintogroup u"
+            ]]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rejoin_group_without_group_asks_nothing() -> Result<()> {
+        let mock_executor = Arc::new(MockExecutor::default());
+        let hyprland = Hyprland::new(mock_executor.clone() as Arc<dyn hyprland::HyprctlExecutor>);
+
+        rejoin_group(&hyprland, "0xALONE", &["0xALONE".to_string()]).await?;
+        rejoin_group(&hyprland, "0xALONE", &[]).await?;
 
+        assert!(mock_executor.dispatched_batches().is_empty());
         Ok(())
     }
 }
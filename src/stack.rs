@@ -2,15 +2,43 @@
 use crate::hyprland::{Hyprland, WindowInfo};
 
 use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
-use std::fs::{File, OpenOptions};
+use std::fs::{self, DirBuilder, File, Metadata, OpenOptions, Permissions};
 use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
 
 use crate::config::Config;
 
-/// Constructs a user-specific temporary filepath using the $USER environment variable.
-fn get_stack_file_path(dir: String, workspace: String) -> Result<PathBuf> {
+/// The directory holding the stack files and control socket by default:
+/// `$XDG_RUNTIME_DIR/hyprland-minimizer`, or `hyprland-minimizer-<uid>` in the temporary
+/// directory when there is no runtime directory, as in `su` or `sudo` shells.
+pub fn runtime_directory() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(runtime_dir) => PathBuf::from(runtime_dir).join("hyprland-minimizer"),
+        None => {
+            // SAFETY: geteuid has no preconditions and cannot fail.
+            let uid = unsafe { libc::geteuid() };
+            env::temp_dir().join(format!("hyprland-minimizer-{uid}"))
+        }
+    }
+}
+
+/// Identifies a stack by the Hyprland instance signature, when known, and workspace.
+fn stack_key(workspace: &str, instance: Option<&str>) -> String {
+    match instance {
+        Some(instance) => format!("{instance}-{workspace}"),
+        None => workspace.to_string(),
+    }
+}
+
+/// Constructs a user-specific filepath in an explicit base directory using the
+/// $USER environment variable, as that directory may be shared with other users.
+fn get_stack_file_path(dir: String, key: String) -> Result<PathBuf> {
     if dir.is_empty() {
         bail!("The base directory was not set.");
     }
@@ -20,13 +48,180 @@ fn get_stack_file_path(dir: String, workspace: String) -> Result<PathBuf> {
             if username.is_empty() {
                 bail!("The USER environment variable was empty.");
             }
-            let file_path = format!("{}/hypr-minimizer-stack-{}-{}", dir, username, workspace);
+            let file_path = format!("{}/hypr-minimizer-stack-{}-{}", dir, username, key);
             Ok(PathBuf::from(file_path))
         }
         Err(_) => bail!("Could not find the USER environment variable."),
     }
 }
 
+/// Creates a directory only its owner can access, or checks that an existing one
+/// belongs to the current user and tightens its permissions.
+pub fn ensure_private_dir(dir: &Path) -> Result<()> {
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .with_context(|| format!("Failed to create directory {:?}", dir))?;
+    let metadata = fs::symlink_metadata(dir)
+        .with_context(|| format!("Failed to inspect directory {:?}", dir))?;
+    if metadata.file_type().is_symlink() {
+        bail!("Refusing to follow the symlink at {:?}", dir);
+    }
+    check_owner(dir, &metadata)?;
+    if metadata.permissions().mode() & 0o077 != 0 {
+        fs::set_permissions(dir, Permissions::from_mode(0o700))
+            .with_context(|| format!("Failed to restrict permissions of {:?}", dir))?;
+    }
+    Ok(())
+}
+
+/// Opens a file without following symlinks, creating it readable by its owner only.
+/// Files owned by another user are refused, as they could have been planted.
+fn open_private(path: &Path, options: &mut OpenOptions) -> Result<File> {
+    let file = match options
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+    {
+        Err(e) if e.raw_os_error() == Some(libc::ELOOP) => {
+            bail!("Refusing to follow the symlink at {:?}", path)
+        }
+        result => result.with_context(|| format!("Failed to open {:?}", path))?,
+    };
+    let metadata = file.metadata()?;
+    check_owner(path, &metadata)?;
+    if metadata.permissions().mode() & 0o077 != 0 {
+        file.set_permissions(Permissions::from_mode(0o600))
+            .with_context(|| format!("Failed to restrict permissions of {:?}", path))?;
+    }
+    Ok(file)
+}
+
+fn check_owner(path: &Path, metadata: &Metadata) -> Result<()> {
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    let uid = unsafe { libc::geteuid() };
+    if metadata.uid() != uid {
+        bail!(
+            "Refusing to use {:?}: it is owned by uid {}, not {}",
+            path,
+            metadata.uid(),
+            uid
+        );
+    }
+    Ok(())
+}
+
+/// The failures of stack operations that callers may want to tell apart.
+#[derive(Debug, Error)]
+pub enum StackError {
+    #[error("No minimized windows in the stack to restore.")]
+    Empty,
+}
+
+/// A record of a minimized window, capturing its state at the time it was minimized.
+/// Stored as one JSON object per line in the stack file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct StackEntry {
+    pub address: String,
+    pub class: String,
+    pub title: String,
+    pub pid: i32,
+    /// The class the window was created with, which unlike `class` never changes.
+    pub initial_class: String,
+    /// When the window's process started, in clock ticks after boot, or 0 if unknown.
+    /// Along with the pid and initial class, it tells the window apart from a later
+    /// one that was given the same address.
+    pub started_at: u64,
+    pub workspace_id: i32,
+    pub workspace_name: String,
+    pub monitor: i32,
+    pub floating: bool,
+    /// The position and size of the window, in global layout coordinates.
+    pub at: [i32; 2],
+    pub size: [i32; 2],
+    pub fullscreen: u8,
+    pub pinned: bool,
+    /// The addresses of the windows in its group when minimized, itself included.
+    pub group: Vec<String>,
+    /// Seconds since the Unix epoch.
+    pub minimized_at: u64,
+}
+
+impl StackEntry {
+    /// Records the current state of a window that is about to be minimized.
+    pub fn from_window(window: &WindowInfo) -> Self {
+        StackEntry {
+            address: window.address.clone(),
+            class: window.class.clone(),
+            title: window.title.clone(),
+            pid: window.pid,
+            initial_class: window.initial_class.clone(),
+            started_at: process_start_time(window.pid).unwrap_or(0),
+            workspace_id: window.workspace.id,
+            workspace_name: window.workspace.name.clone(),
+            monitor: window.monitor,
+            floating: window.floating,
+            at: window.at,
+            size: window.size,
+            fullscreen: window.fullscreen,
+            pinned: window.pinned,
+            group: window.grouped.clone(),
+            minimized_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+        }
+    }
+
+    /// Whether `window` is the window this entry was recorded for, rather than one
+    /// that reuses its address. Entries recorded without a fingerprint can't tell.
+    pub fn identifies(&self, window: &WindowInfo) -> bool {
+        if self.pid == 0 {
+            return true;
+        }
+        self.pid == window.pid
+            && (self.initial_class.is_empty() || self.initial_class == window.initial_class)
+            && (self.started_at == 0
+                || process_start_time(window.pid).is_none_or(|started| started == self.started_at))
+    }
+
+    /// Parses a line of the stack file. Older versions stored bare addresses, which
+    /// are read as entries without metadata and rewritten as records on the next write.
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() {
+            None
+        } else if line.starts_with('{') {
+            serde_json::from_str(line).ok()
+        } else {
+            Some(StackEntry::from(line))
+        }
+    }
+}
+
+/// When a process started, in clock ticks after boot, as the 22nd field of
+/// `/proc/<pid>/stat`.
+fn process_start_time(pid: i32) -> Option<u64> {
+    if pid <= 0 {
+        return None;
+    }
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The command name, second field, is in parentheses and may contain spaces.
+    let fields = &stat[stat.rfind(')')? + 1..];
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+/// An entry that only knows the window's address.
+impl From<&str> for StackEntry {
+    fn from(address: &str) -> Self {
+        StackEntry {
+            address: address.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
 // Represents the stack file.
 #[derive(Debug, Clone)]
 pub struct Stack {
@@ -39,96 +234,224 @@ impl Stack {
         Stack { path: path.into() }
     }
 
-    /// Creates a Stack instance by determining the user-specific default path.
-    /// This can fail if the user cannot be determined from the environment.
-    pub fn at_default_path(config: Config) -> Result<Self> {
-        let path = get_stack_file_path(
-            config.stack_base_directory.unwrap(),
-            config.workspace.unwrap(),
-        )?;
+    /// Creates a Stack instance in `stack_base_directory`, or in the user's private
+    /// runtime directory when it is not set. The file is keyed by the Hyprland
+    /// instance, so concurrent sessions keep separate stacks. This can fail if the
+    /// user cannot be determined from the environment, or the directory is not private.
+    pub fn at_default_path(config: Config, instance: Option<&str>) -> Result<Self> {
+        let key = stack_key(&config.workspace.unwrap(), instance);
+        let path = match config.stack_base_directory {
+            Some(dir) => get_stack_file_path(dir, key)?,
+            None => {
+                let dir = runtime_directory();
+                ensure_private_dir(&dir)?;
+                dir.join(format!("stack-{key}"))
+            }
+        };
 
         Ok(Stack { path })
     }
 
-    /// Pushes a new address onto the stack file.
-    pub fn push(&self, address: &str) -> Result<()> {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.path)
-            .context("Failed to open stack file for appending")?;
-        writeln!(file, "{address}").context("Failed to append address to stack file")
+    /// Pushes a new entry onto the stack file.
+    pub fn push(&self, entry: impl Into<StackEntry>) -> Result<()> {
+        let entry = entry.into();
+        self.with_lock(|| {
+            let mut stack = read_stack(&self.path)?;
+            stack.push(entry);
+            write_stack(&self.path, &stack)
+        })
     }
 
     /// Removes a specific address from anywhere in the stack file.
     pub fn remove(&self, address: &str) -> Result<()> {
-        if !self.path.exists() {
-            return Ok(());
-        }
-        let stack = read_stack(&self.path)?;
-        let new_stack: Vec<String> = stack.into_iter().filter(|a| a.trim() != address).collect();
-        write_stack(&self.path, &new_stack)
+        self.with_lock(|| {
+            if !self.path.exists() {
+                return Ok(());
+            }
+            let stack = read_stack(&self.path)?;
+            let new_stack: Vec<StackEntry> =
+                stack.into_iter().filter(|e| e.address != address).collect();
+            write_stack(&self.path, &new_stack)
+        })
     }
 
-    /// Pops the last address from the stack file.
-    pub fn pop(&self) -> Result<Option<String>> {
-        if !self.path.exists() {
-            return Ok(None);
-        }
-        let mut stack = read_stack(&self.path)?;
-        let last = stack.pop();
-        if last.is_some() {
-            write_stack(&self.path, &stack)?;
-        }
-        Ok(last)
+    /// Pops the last entry from the stack file.
+    pub fn pop(&self) -> Result<Option<StackEntry>> {
+        self.with_lock(|| {
+            if !self.path.exists() {
+                return Ok(None);
+            }
+            let mut stack = read_stack(&self.path)?;
+            let last = stack.pop();
+            if last.is_some() {
+                write_stack(&self.path, &stack)?;
+            }
+            Ok(last)
+        })
     }
 
-    /// Get all minimized windows
-    pub fn minimized(&self, hyprland: &Hyprland) -> Result<Vec<WindowInfo>> {
-        let stack = read_stack(&self.path)?;
+    /// Get the entries of all minimized windows that still exist, pruning the entries
+    /// of windows that were closed after being minimized from the file, including
+    /// those whose address was since given to another window.
+    pub async fn minimized(&self, hyprland: &Hyprland) -> Result<Vec<StackEntry>> {
+        let open = self.open_entries(hyprland).await?;
+        Ok(open.into_iter().map(|(entry, _)| entry).collect())
+    }
 
-        let windows: Vec<WindowInfo> = stack
-            .iter()
-            .filter_map(|addr| {
-                // Using filter_map to safely ignore any errors, which can happen if a window was
-                // closed after being minimized.
-                hyprland.get_window_by_address(addr).ok()
-            })
-            .collect();
+    /// Like `minimized`, but only the windows still in `workspace`, the minimized
+    /// workspace: a window restored by other means stays on the stack until pruned.
+    pub async fn minimized_in(
+        &self,
+        hyprland: &Hyprland,
+        workspace: &str,
+    ) -> Result<Vec<StackEntry>> {
+        let open = self.open_entries(hyprland).await?;
+        Ok(open
+            .into_iter()
+            .filter(|(_, window)| window.workspace.name == workspace)
+            .map(|(entry, _)| entry)
+            .collect())
+    }
+
+    /// The entries of the windows that still exist, each with its window, pruning
+    /// the others from the file.
+    async fn open_entries(&self, hyprland: &Hyprland) -> Result<Vec<(StackEntry, WindowInfo)>> {
+        // Writes replace the file atomically, so reading needs no lock.
+        let known = read_stack(&self.path)?;
+        // A single snapshot of the clients, joined against the stack.
+        let clients: Vec<WindowInfo> = hyprland
+            .exec("clients")
+            .await
+            .context("Failed to get client list from Hyprland.")?;
+        let open: HashMap<&str, &WindowInfo> =
+            clients.iter().map(|c| (c.address.as_str(), c)).collect();
+
+        // A window now at the address of a closed one is not the one minimized.
+        let mut entries = Vec::new();
+        let mut stale = Vec::new();
+        for entry in known {
+            match open.get(entry.address.as_str()) {
+                Some(window) if entry.identifies(window) => {
+                    entries.push((entry, (*window).clone()))
+                }
+                _ => stale.push(entry),
+            }
+        }
+        if !stale.is_empty() {
+            // Only drop the entries seen before the snapshot, as windows minimized
+            // meanwhile are missing from it without being closed.
+            self.with_lock(|| {
+                let stack: Vec<StackEntry> = read_stack(&self.path)?
+                    .into_iter()
+                    .filter(|entry| !stale.contains(entry))
+                    .collect();
+                write_stack(&self.path, &stack)
+            })?;
+        }
+        Ok(entries)
+    }
 
-        Ok(windows)
+    /// Runs `operation` while holding an exclusive advisory lock shared by every
+    /// process using this stack. The lock lives in a sibling `.lock` file because
+    /// the stack file itself is replaced on every write.
+    fn with_lock<T>(&self, operation: impl FnOnce() -> Result<T>) -> Result<T> {
+        let lock_file = open_private(
+            &sibling_path(&self.path, "lock"),
+            OpenOptions::new().create(true).truncate(false).write(true),
+        )
+        .context("Failed to open stack lock file")?;
+        lock_file.lock().context("Failed to lock stack file")?;
+        // The lock is released when `lock_file` is dropped.
+        operation()
     }
 }
 
-fn read_stack(path: &Path) -> Result<Vec<String>> {
+/// Appends an extension to the file name, e.g. `stack` becomes `stack.lock`.
+fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+fn read_stack(path: &Path) -> Result<Vec<StackEntry>> {
     if !path.exists() {
         return Ok(Vec::new());
     }
-    let file = File::open(path).context("Failed to open stack file for reading")?;
+    let file = open_private(path, OpenOptions::new().read(true))
+        .context("Failed to open stack file for reading")?;
     let reader = BufReader::new(file);
-    reader
+    let lines = reader
         .lines()
-        .collect::<Result<_, _>>()
-        .context("Failed to read lines from stack file")
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to read lines from stack file")?;
+    Ok(lines
+        .iter()
+        .filter_map(|line| StackEntry::parse(line))
+        .collect())
 }
 
-fn write_stack(path: &Path, stack: &[String]) -> Result<()> {
-    let mut file = File::create(path).context("Failed to open stack file for writing")?;
-    for address in stack {
-        writeln!(file, "{address}").context("Failed to write address to stack file")?;
+/// Writes the stack to a temporary file and renames it over the stack file, so
+/// readers never observe a half-written stack. Callers must hold the lock.
+fn write_stack(path: &Path, stack: &[StackEntry]) -> Result<()> {
+    let temp_path = sibling_path(path, "tmp");
+    let mut file = open_private(
+        &temp_path,
+        OpenOptions::new().create(true).truncate(true).write(true),
+    )
+    .context("Failed to open stack file for writing")?;
+    for entry in stack {
+        let record = serde_json::to_string(entry).context("Failed to serialize stack entry")?;
+        writeln!(file, "{record}").context("Failed to write entry to stack file")?;
     }
-    Ok(())
+    file.sync_all().context("Failed to flush stack file")?;
+    fs::rename(&temp_path, path).context("Failed to replace stack file")
 }
 
 // --- Unit Tests ---
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hyprland::{self, Workspace};
+    use async_trait::async_trait;
     use std::env;
-    use tempfile::NamedTempFile;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{ExitStatus, Output};
+    use std::sync::{Arc, Mutex};
+    use tempfile::{NamedTempFile, tempdir};
+
+    // --- Mocking Setup ---
+
+    /// Answers every json command with the same reply and records the commands.
+    #[derive(Default, Clone)]
+    struct MockExecutor {
+        json_response: String,
+        json_commands: Arc<Mutex<Vec<String>>>,
+    }
+    #[async_trait]
+    impl hyprland::HyprctlExecutor for MockExecutor {
+        async fn execute_json(&self, command: &str) -> Result<Output> {
+            self.json_commands.lock().unwrap().push(command.to_string());
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: self.json_response.as_bytes().to_vec(),
+                stderr: vec![],
+            })
+        }
+        async fn execute_dispatch(&self, _command: &str) -> Result<Output> {
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: vec![],
+                stderr: vec![],
+            })
+        }
+        async fn execute_batch(&self, _commands: &[String]) -> Result<Output> {
+            self.execute_dispatch("").await
+        }
+    }
 
     #[test]
-    fn at_default_path_success_when_user_is_set() {
+    fn at_base_directory_success_when_user_is_set() {
         // --- Setup ---
         // Set a temporary environment variable for this test.
         let test_user = "testuser";
@@ -138,7 +461,13 @@ mod tests {
 
         // --- Execute ---
         // Call the function we want to test.
-        let result = Stack::at_default_path(Config::default());
+        let result = Stack::at_default_path(
+            Config {
+                stack_base_directory: Some("/tmp".to_string()),
+                ..Config::default()
+            },
+            None,
+        );
 
         // --- Assert ---
         // Ensure the function returned an Ok variant.
@@ -163,7 +492,7 @@ mod tests {
     }
 
     #[test]
-    fn at_default_path_fails_when_user_is_not_set() {
+    fn at_base_directory_fails_when_user_is_not_set() {
         // --- Setup ---
         // Ensure the environment variable is not set.
         unsafe {
@@ -171,7 +500,13 @@ mod tests {
         }
 
         // --- Execute ---
-        let result = Stack::at_default_path(Config::default());
+        let result = Stack::at_default_path(
+            Config {
+                stack_base_directory: Some("/tmp".to_string()),
+                ..Config::default()
+            },
+            None,
+        );
 
         // --- Assert ---
         // Ensure the function returned an Err variant.
@@ -185,6 +520,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_stacks_are_keyed_by_instance() {
+        assert_eq!(stack_key("special:minimized", None), "special:minimized");
+        assert_eq!(
+            stack_key("special:minimized", Some("abc_1_2")),
+            "abc_1_2-special:minimized"
+        );
+        assert_ne!(
+            stack_key("special:minimized", Some("abc_1_2")),
+            stack_key("special:minimized", Some("def_3_4"))
+        );
+    }
+
     #[test]
     fn test_stack_operations() -> Result<()> {
         let temp_file = NamedTempFile::new()?;
@@ -196,8 +544,8 @@ mod tests {
         stack.push("addr2")?;
         stack.push("addr3")?;
 
-        assert_eq!(stack.pop()?.unwrap(), "addr3");
-        assert_eq!(stack.pop()?.unwrap(), "addr2");
+        assert_eq!(stack.pop()?.unwrap().address, "addr3");
+        assert_eq!(stack.pop()?.unwrap().address, "addr2");
 
         stack.push("addr2-restored")?;
         stack.push("addr3-restored")?;
@@ -205,10 +553,295 @@ mod tests {
         stack.remove("addr2-restored")?;
         // Stack should be: [addr1, addr3-restored]
 
-        assert_eq!(stack.pop()?.unwrap(), "addr3-restored");
-        assert_eq!(stack.pop()?.unwrap(), "addr1");
+        assert_eq!(stack.pop()?.unwrap().address, "addr3-restored");
+        assert_eq!(stack.pop()?.unwrap().address, "addr1");
         assert!(stack.pop()?.is_none());
 
         Ok(())
     }
+
+    #[test]
+    fn test_concurrent_operations_lose_no_address() -> Result<()> {
+        const THREADS: usize = 16;
+        const PUSHES_PER_THREAD: usize = 25;
+
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("stack");
+
+        // Every thread uses its own Stack, as separate processes would.
+        let pushers: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let stack = Stack::new(&path);
+                std::thread::spawn(move || {
+                    for push in 0..PUSHES_PER_THREAD {
+                        stack.push(format!("0x{thread}-{push}").as_str()).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for pusher in pushers {
+            pusher.join().unwrap();
+        }
+
+        let mut expected: Vec<String> = (0..THREADS)
+            .flat_map(|thread| (0..PUSHES_PER_THREAD).map(move |push| format!("0x{thread}-{push}")))
+            .collect();
+        let mut stored: Vec<String> = read_stack(&path)?
+            .into_iter()
+            .map(|entry| entry.address)
+            .collect();
+        expected.sort();
+        stored.sort();
+        assert_eq!(stored, expected);
+
+        // Concurrent pops must hand out every address exactly once.
+        let poppers: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let stack = Stack::new(&path);
+                std::thread::spawn(move || {
+                    let mut popped = Vec::new();
+                    while let Some(entry) = stack.pop().unwrap() {
+                        popped.push(entry.address);
+                    }
+                    popped
+                })
+            })
+            .collect();
+        let mut popped: Vec<String> = poppers
+            .into_iter()
+            .flat_map(|popper| popper.join().unwrap())
+            .collect();
+        popped.sort();
+        assert_eq!(popped, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_entries_round_trip_with_metadata() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let stack = Stack::new(temp_file.path());
+        let window = WindowInfo {
+            address: "0xRECORD".to_string(),
+            workspace: Workspace {
+                id: 3,
+                name: "code".to_string(),
+                ..Default::default()
+            },
+            title: "Editor".to_string(),
+            class: "code".to_string(),
+            pid: 4242,
+            monitor: 1,
+            floating: true,
+            fullscreen: 0,
+            ..Default::default()
+        };
+
+        stack.push(StackEntry::from_window(&window))?;
+        let entry = stack.pop()?.unwrap();
+
+        assert_eq!(entry.address, "0xRECORD");
+        assert_eq!(entry.title, "Editor");
+        assert_eq!(entry.pid, 4242);
+        assert_eq!(entry.workspace_id, 3);
+        assert_eq!(entry.workspace_name, "code");
+        assert_eq!(entry.monitor, 1);
+        assert!(entry.floating);
+        assert!(entry.minimized_at > 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_plain_address_file_is_migrated() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        fs::write(temp_file.path(), "0xOLD1\n0xOLD2\n")?;
+        let stack = Stack::new(temp_file.path());
+
+        stack.push(StackEntry {
+            title: "New".to_string(),
+            ..StackEntry::from("0xNEW")
+        })?;
+
+        // Every line is now a JSON record, old entries included.
+        let content = fs::read_to_string(temp_file.path())?;
+        let records: Vec<StackEntry> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let addresses: Vec<&str> = records.iter().map(|e| e.address.as_str()).collect();
+        assert_eq!(addresses, ["0xOLD1", "0xOLD2", "0xNEW"]);
+        assert_eq!(records[2].title, "New");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_private_dir_is_owner_only() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let created = temp_dir.path().join("created");
+        let loose = temp_dir.path().join("loose");
+        fs::create_dir(&loose)?;
+        fs::set_permissions(&loose, Permissions::from_mode(0o755))?;
+
+        ensure_private_dir(&created)?;
+        ensure_private_dir(&loose)?;
+
+        assert_eq!(fs::metadata(&created)?.permissions().mode() & 0o777, 0o700);
+        assert_eq!(fs::metadata(&loose)?.permissions().mode() & 0o777, 0o700);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stack_files_are_owner_only() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("stack");
+        let stack = Stack::new(&path);
+
+        stack.push("0xPRIVATE")?;
+
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+        let lock_path = sibling_path(&path, "lock");
+        assert_eq!(fs::metadata(lock_path)?.permissions().mode() & 0o777, 0o600);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_symlinked_stack_files_are_refused() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let target = temp_dir.path().join("target");
+        fs::write(&target, "0xVICTIM\n")?;
+        let path = temp_dir.path().join("stack");
+        std::os::unix::fs::symlink(&target, &path)?;
+        let stack = Stack::new(&path);
+
+        let error = stack.pop().unwrap_err();
+
+        assert!(format!("{error:#}").contains("Refusing to follow the symlink"));
+        assert_eq!(fs::read_to_string(&target)?, "0xVICTIM\n");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_minimized_uses_one_snapshot_and_prunes_closed_windows() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let stack = Stack::new(temp_file.path());
+        for address in ["0xOPEN1", "0xCLOSED", "0xOPEN2", "0xGONE"] {
+            stack.push(address)?;
+        }
+
+        let executor = MockExecutor {
+            json_response: r#"[
+                {"address": "0xOPEN1", "workspace": {"id": -99, "name": "special:minimized"}, "title": "One", "class": "a"},
+                {"address": "0xOPEN2", "workspace": {"id": -99, "name": "special:minimized"}, "title": "Two", "class": "b"},
+                {"address": "0xOTHER", "workspace": {"id": 1}, "title": "Other", "class": "c"}
+            ]"#
+            .to_string(),
+            ..Default::default()
+        };
+        let hyprland = Hyprland::new(Arc::new(executor.clone()));
+
+        let minimized = stack.minimized(&hyprland).await?;
+
+        let addresses: Vec<&str> = minimized.iter().map(|e| e.address.as_str()).collect();
+        assert_eq!(addresses, ["0xOPEN1", "0xOPEN2"]);
+        assert_eq!(*executor.json_commands.lock().unwrap(), ["clients"]);
+
+        // The closed windows were pruned from the file as well.
+        let stored: Vec<String> = read_stack(temp_file.path())?
+            .into_iter()
+            .map(|entry| entry.address)
+            .collect();
+        assert_eq!(stored, ["0xOPEN1", "0xOPEN2"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_minimized_in_leaves_out_windows_restored_by_other_means() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let stack = Stack::new(temp_file.path());
+        for address in ["0xHIDDEN", "0xBACK"] {
+            stack.push(address)?;
+        }
+
+        let executor = MockExecutor {
+            json_response: r#"[
+                {"address": "0xHIDDEN", "workspace": {"id": -99, "name": "special:minimized"}, "title": "One", "class": "a"},
+                {"address": "0xBACK", "workspace": {"id": 2, "name": "2"}, "title": "Two", "class": "b"}
+            ]"#
+            .to_string(),
+            ..Default::default()
+        };
+        let hyprland = Hyprland::new(Arc::new(executor));
+
+        let minimized = stack.minimized_in(&hyprland, "special:minimized").await?;
+
+        let addresses: Vec<&str> = minimized.iter().map(|e| e.address.as_str()).collect();
+        assert_eq!(addresses, ["0xHIDDEN"]);
+        // Only closed windows are pruned, so the restored one keeps its entry.
+        assert_eq!(read_stack(temp_file.path())?.len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_minimized_prunes_windows_with_a_recycled_address() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let stack = Stack::new(temp_file.path());
+        stack.push(StackEntry {
+            pid: 100,
+            initial_class: "firefox".to_string(),
+            ..StackEntry::from("0xREUSED")
+        })?;
+        stack.push(StackEntry {
+            pid: 200,
+            initial_class: "kitty".to_string(),
+            ..StackEntry::from("0xSAME")
+        })?;
+
+        let executor = MockExecutor {
+            json_response: r#"[
+                {"address": "0xREUSED", "workspace": {"id": -99, "name": "special:minimized"}, "title": "New", "class": "mpv", "initialClass": "mpv", "pid": 300},
+                {"address": "0xSAME", "workspace": {"id": -99, "name": "special:minimized"}, "title": "Term", "class": "kitty", "initialClass": "kitty", "pid": 200}
+            ]"#
+            .to_string(),
+            ..Default::default()
+        };
+        let hyprland = Hyprland::new(Arc::new(executor));
+
+        let minimized = stack.minimized(&hyprland).await?;
+
+        let addresses: Vec<&str> = minimized.iter().map(|e| e.address.as_str()).collect();
+        assert_eq!(addresses, ["0xSAME"]);
+        assert_eq!(read_stack(temp_file.path())?, minimized);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprint_includes_process_start_time() {
+        let window = WindowInfo {
+            address: "0xSELF".to_string(),
+            initial_class: "self".to_string(),
+            pid: std::process::id() as i32,
+            ..Default::default()
+        };
+        let entry = StackEntry::from_window(&window);
+        assert!(entry.started_at > 0);
+        assert!(entry.identifies(&window));
+
+        // The same pid and class, from a process started at another time.
+        let restarted = StackEntry {
+            started_at: entry.started_at + 1,
+            ..entry.clone()
+        };
+        assert!(!restarted.identifies(&window));
+
+        // Entries recorded before fingerprints existed match any window.
+        assert!(StackEntry::from("0xSELF").identifies(&window));
+    }
 }